@@ -1,6 +1,8 @@
 use {
+    crate::{AddressType, RelationType},
     anyhow::{anyhow, bail},
     clap::{Parser, Subcommand},
+    country_codes::CountryCode,
     std::{env, path::PathBuf, str::FromStr},
 };
 
@@ -10,6 +12,14 @@ pub struct Args {
     command: Command,
     #[arg(short = 's', long = "store")]
     store_path: Option<PathBuf>,
+    /// Skip invalid contacts instead of failing, printing a warning for each problem
+    #[arg(long = "lenient")]
+    lenient: bool,
+    /// Only include contacts with this tag, may be given multiple times
+    ///
+    /// Applies to the names, bdays, bdays-calendar, export, labels and search commands.
+    #[arg(long = "tag", global = true)]
+    tags: Vec<String>,
 }
 
 impl Args {
@@ -17,6 +27,14 @@ impl Args {
         &self.command
     }
 
+    pub fn lenient(&self) -> bool {
+        self.lenient
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     pub fn store_path(&self) -> anyhow::Result<PathBuf> {
         self.store_path
             .clone()
@@ -33,22 +51,225 @@ impl Args {
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
+    /// Get a list of the addresses of all contacts, formatted according to their country
+    Addresses,
     /// Get a list containing the next birthday of every contact, in chronological order
     Bdays,
     /// Create an iCalendar file containing the future birthdays of all contacts
     BdaysCalendar,
+    /// Store the contacts of an encrypted store unencrypted again
+    Decrypt,
+    /// Delete a contact
+    ///
+    /// Relations of other contacts pointing to the deleted contact are reported, but kept.
+    Delete {
+        /// The name (case-insensitive) or the UID of the contact
+        ///
+        /// A contact whose full name is the query is chosen over contacts whose names only contain
+        /// it. If several contacts match, the UID has to be given.
+        query: String,
+    },
+    /// Encrypt the contact store with age
+    ///
+    /// The store is encrypted to the recipients in the configuration or, if there are none, with a
+    /// passphrase. Afterwards, it is decrypted transparently, with the passphrase read from the
+    /// environment variable TACTFUL_PASSPHRASE, printed by the shell command in the environment
+    /// variable TACTFUL_PASSPHRASE_COMMAND or read from a prompt. Encrypting an encrypted store
+    /// again changes its passphrase or recipients.
+    Encrypt,
     /// Output contacts to STDOUT in the given format (by default vCard)
+    ///
+    /// The DOT format draws the relations between the contacts as a Graphviz graph.
     Export {
-        /// The format of the output (vcard/json)
+        /// The format of the output (vcard/json/dot)
         #[arg(short = 'f', long = "fmt", default_value = "vcard")]
         format: OutputFormat,
+        /// Only export addresses of the given type (home/work/other), may be given multiple times
+        #[arg(long = "address-type")]
+        address_types: Vec<AddressType>,
+        /// Only export relations of the given type, may be given multiple times
+        #[arg(long = "relation-type")]
+        relation_types: Vec<RelationType>,
+        /// Only export contacts whose organization contains this text (case-insensitive)
+        #[arg(long = "org")]
+        org: Option<String>,
+        /// Also export a vCard of kind "group" for every tag, listing its members (vCard only)
+        #[arg(long = "groups")]
+        groups: bool,
+        /// How to export photos in vCards (embed/uri)
+        ///
+        /// "embed" includes the image data, "uri" links to the file in the photos directory.
+        #[arg(long = "photo-mode", default_value = "embed")]
+        photo_mode: PhotoMode,
+        /// The vCard version to export (2.1/3.0/4.0)
+        ///
+        /// Use 3.0 or 2.1 for older clients such as car kits and feature phones. Properties that
+        /// only exist in vCard 4.0, e.g. relations, languages and gender, are left out.
+        #[arg(long = "vcard-version", default_value = "4.0")]
+        vcard_version: VcardVersion,
+        /// Write one .vcf file per contact into this directory instead of STDOUT (vCard only)
+        ///
+        /// Files are only written if their content changed. Files written by a previous export of
+        /// contacts that no longer exist are removed, unless the contacts are filtered by tags or
+        /// organization. Other files in the directory are left alone.
+        #[arg(long = "split-dir")]
+        split_dir: Option<PathBuf>,
+    },
+    /// Import the contacts of a vCard file into the store
+    ///
+    /// Properties tactful does not understand are kept and exported again as they are. Contacts
+    /// whose UID is already in the store replace the stored contact, so a file can be imported
+    /// again after it changed.
+    Import {
+        path: PathBuf,
+        /// The format of the file (vcard/json)
+        ///
+        /// JSON files have the format of `contacts.json`, e.g. as created by `export --fmt json`.
+        #[arg(short = 'f', long = "fmt", default_value = "vcard")]
+        format: InputFormat,
+    },
+    /// Create mailing labels for the addresses of contacts
+    ///
+    /// The most preferred address of each contact is used. Contacts without a matching address are
+    /// listed on STDERR.
+    Labels {
+        /// Only create labels for contacts whose name contains this text (case-insensitive)
+        query: Option<String>,
+        /// The format of the output (text/csv/svg/pdf)
+        #[arg(short = 'f', long = "fmt", default_value = "text")]
+        format: LabelFormat,
+        /// The label sheet to use for SVG and PDF output (l7160/5160)
+        #[arg(long = "sheet", default_value = "l7160")]
+        sheet: LabelSheet,
+        /// Only use addresses of the given type (home/work/other), may be given multiple times
+        #[arg(long = "address-type")]
+        address_types: Vec<AddressType>,
+        /// The country the letters are sent from (by default the configured default region)
+        ///
+        /// The country is omitted from the labels of domestic addresses.
+        #[arg(long = "from", value_parser = parse_country_code)]
+        origin: Option<CountryCode>,
     },
     /// Get a list of the names of all contacts
-    Names,
+    Names {
+        /// Only list contacts whose organization contains this text (case-insensitive)
+        #[arg(long = "org")]
+        org: Option<String>,
+    },
+    /// Show the local time of contacts
+    ///
+    /// Contacts without a time zone are listed as such, unless the time zone can be derived from
+    /// their country (see the `derive_time_zone` configuration option).
+    Now {
+        /// Only show contacts whose name contains this text (case-insensitive)
+        query: Option<String>,
+    },
+    /// Get a list of the phone numbers of all contacts
+    Phones {
+        /// The format of the phone numbers (international/national/e164)
+        #[arg(short = 'f', long = "fmt", default_value = "international")]
+        format: PhoneNumberFormat,
+    },
+    /// Output a JSON schema describing the format of the contact store
+    Schema,
+    /// Find contacts by their name, email addresses, notes or addresses
+    ///
+    /// All words of the query must match. SQLite stores use their full-text index, which matches
+    /// the words of the query at the start of words.
+    Search { query: String },
+    /// List all tags along with the number of contacts having them, or rename and merge tags
+    Tags {
+        #[command(subcommand)]
+        command: Option<TagsCommand>,
+    },
+    /// Check the contact store for problems and report all of them
+    Validate,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TagsCommand {
+    /// Merge several tags into a single one
+    Merge {
+        /// The tags to merge
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// The tag replacing the merged tags, may be one of them or a new one
+        #[arg(long = "into")]
+        into: String,
+    },
+    /// Rename a tag. If the new tag already exists, both are merged.
+    Rename { from: String, to: String },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PhotoMode {
+    Embed,
+    Uri,
+    /// Reference photos by their path relative to the store, e.g. "photos/anna.jpg", without
+    /// reading them. This is how the vdir backend stores photos, it cannot be chosen for exports.
+    Store,
+}
+
+impl FromStr for PhotoMode {
+    type Err = anyhow::Error;
+    fn from_str(mode: &str) -> anyhow::Result<Self> {
+        Ok(match mode.to_ascii_lowercase().as_str() {
+            "embed" => PhotoMode::Embed,
+            "uri" => PhotoMode::Uri,
+            _ => bail!("Invalid photo mode"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VcardVersion {
+    V2_1,
+    V3_0,
+    V4_0,
+}
+
+impl VcardVersion {
+    pub fn name(self) -> &'static str {
+        match self {
+            VcardVersion::V2_1 => "2.1",
+            VcardVersion::V3_0 => "3.0",
+            VcardVersion::V4_0 => "4.0",
+        }
+    }
+}
+
+impl FromStr for VcardVersion {
+    type Err = anyhow::Error;
+    fn from_str(version: &str) -> anyhow::Result<Self> {
+        Ok(match version {
+            "2.1" => VcardVersion::V2_1,
+            "3.0" => VcardVersion::V3_0,
+            "4.0" => VcardVersion::V4_0,
+            _ => bail!("Invalid vCard version"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    Json,
+    Vcard,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+    fn from_str(format: &str) -> anyhow::Result<Self> {
+        Ok(match format.to_ascii_lowercase().as_str() {
+            "json" => InputFormat::Json,
+            "vcard" => InputFormat::Vcard,
+            _ => bail!("Invalid input format"),
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum OutputFormat {
+    Dot,
     Json,
     Vcard,
 }
@@ -57,9 +278,75 @@ impl FromStr for OutputFormat {
     type Err = anyhow::Error;
     fn from_str(format: &str) -> anyhow::Result<Self> {
         Ok(match format.to_ascii_lowercase().as_str() {
+            "dot" => OutputFormat::Dot,
             "json" => OutputFormat::Json,
             "vcard" => OutputFormat::Vcard,
             _ => bail!("Invalid output format"),
         })
     }
 }
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PhoneNumberFormat {
+    E164,
+    International,
+    National,
+}
+
+impl FromStr for PhoneNumberFormat {
+    type Err = anyhow::Error;
+    fn from_str(format: &str) -> anyhow::Result<Self> {
+        Ok(match format.to_ascii_lowercase().as_str() {
+            "e164" => PhoneNumberFormat::E164,
+            "international" => PhoneNumberFormat::International,
+            "national" => PhoneNumberFormat::National,
+            _ => bail!("Invalid phone number format"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LabelFormat {
+    Text,
+    Csv,
+    Svg,
+    Pdf,
+}
+
+impl FromStr for LabelFormat {
+    type Err = anyhow::Error;
+    fn from_str(format: &str) -> anyhow::Result<Self> {
+        Ok(match format.to_ascii_lowercase().as_str() {
+            "text" => LabelFormat::Text,
+            "csv" => LabelFormat::Csv,
+            "svg" => LabelFormat::Svg,
+            "pdf" => LabelFormat::Pdf,
+            _ => bail!("Invalid label format"),
+        })
+    }
+}
+
+/// A sheet of labels
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LabelSheet {
+    /// Avery L7160 (A4, 21 labels)
+    AveryL7160,
+    /// Avery 5160 (US Letter, 30 labels)
+    Avery5160,
+}
+
+impl FromStr for LabelSheet {
+    type Err = anyhow::Error;
+    fn from_str(sheet: &str) -> anyhow::Result<Self> {
+        Ok(match sheet.to_ascii_lowercase().as_str() {
+            "l7160" => LabelSheet::AveryL7160,
+            "5160" => LabelSheet::Avery5160,
+            _ => bail!("Invalid label sheet"),
+        })
+    }
+}
+
+fn parse_country_code(alpha2: &str) -> anyhow::Result<CountryCode> {
+    country_codes::from_alpha2(&alpha2.to_ascii_uppercase())
+        .ok_or_else(|| anyhow!("Invalid country code"))
+}
@@ -0,0 +1,96 @@
+//! Configuration of tactful
+//!
+//! The configuration is read from the file `config.json` in the contact store. The file is
+//! optional and so are all of its options.
+
+use {
+    anyhow::Context,
+    country_codes::CountryCode,
+    serde::{de::Error, Deserialize, Deserializer},
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Skip invalid contacts instead of failing (see `--lenient`)
+    lenient: bool,
+    /// The region (ISO 3166-1 alpha-2 country code) used to resolve phone numbers in national
+    /// format if the contact has no addresses
+    #[serde(deserialize_with = "deserialize_region")]
+    default_region: Option<CountryCode>,
+    /// Derive the time zone of contacts without one from the country of their most preferred
+    /// address, if the country has a single time zone
+    derive_time_zone: bool,
+    /// Keep the contacts in a vdir (a directory with one vCard file per contact, as used by
+    /// vdirsyncer and khard) instead of `contacts.json`. Relative paths are resolved against the
+    /// contact store.
+    vdir: Option<PathBuf>,
+    /// Keep the contacts in an SQLite database with full-text search instead of `contacts.json`.
+    /// Relative paths are resolved against the contact store. Requires the `sqlite` feature.
+    sqlite: Option<PathBuf>,
+    /// The age identity file used to decrypt a store encrypted to recipients. Relative paths are
+    /// resolved against the contact store.
+    identity: Option<PathBuf>,
+    /// The age recipients (X25519 public keys) `tactful encrypt` encrypts the store to. Without
+    /// recipients, the store is encrypted with a passphrase.
+    recipients: Vec<String>,
+}
+
+impl Config {
+    /// Read the configuration of the store located at the given path
+    pub fn from_store_path<P: AsRef<Path>>(store_path: P) -> anyhow::Result<Self> {
+        let config_path = store_path.as_ref().join("config.json");
+        match fs::read_to_string(&config_path) {
+            Ok(config) => serde_json::from_str(&config).with_context(|| {
+                format!("Failed to parse configuration at {}", config_path.display())
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error).with_context(|| {
+                format!("Failed to read configuration at {}", config_path.display())
+            }),
+        }
+    }
+
+    pub fn lenient(&self) -> bool {
+        self.lenient
+    }
+
+    pub fn default_region(&self) -> Option<CountryCode> {
+        self.default_region
+    }
+
+    pub fn derive_time_zone(&self) -> bool {
+        self.derive_time_zone
+    }
+
+    pub fn vdir(&self) -> Option<&Path> {
+        self.vdir.as_deref()
+    }
+
+    pub fn sqlite(&self) -> Option<&Path> {
+        self.sqlite.as_deref()
+    }
+
+    pub fn identity(&self) -> Option<&Path> {
+        self.identity.as_deref()
+    }
+
+    pub fn recipients(&self) -> &[String] {
+        &self.recipients
+    }
+}
+
+fn deserialize_region<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<CountryCode>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|region| {
+            country_codes::from_alpha2(&region)
+                .ok_or_else(|| D::Error::custom(format!("Invalid region \"{region}\"")))
+        })
+        .transpose()
+}
@@ -0,0 +1,69 @@
+//! Graphviz export of relations
+//!
+//! This module renders the relations between contacts as a directed graph in the DOT language, e.g.
+//! to get a family tree or an org chart. Every contact is a node, and every relation is an edge
+//! from the contact to the related person, labeled with the type of the relation. People that are
+//! not in the store are drawn as dashed nodes.
+
+use {
+    crate::{Contact, RelationTarget},
+    std::{collections::HashSet, io::Write},
+};
+
+pub fn contacts_to_dot<W: Write>(mut writer: W, contacts: &[Contact]) -> anyhow::Result<()> {
+    let uids = contacts.iter().map(Contact::uid).collect::<Vec<_>>();
+    let known_uids = uids.iter().copied().collect::<HashSet<_>>();
+
+    writeln!(writer, "digraph relations {{")?;
+    writeln!(writer, "    node [shape=box];")?;
+    for (contact, uid) in contacts.iter().zip(&uids) {
+        writeln!(
+            writer,
+            "    {} [label={}];",
+            quote(uid),
+            quote(&contact.name.to_string())
+        )?;
+    }
+
+    let mut people = HashSet::new();
+    for (contact, uid) in contacts.iter().zip(&uids) {
+        for relation in &contact.relations {
+            let target = match &relation.target {
+                // Relations to contacts that are not exported are left out
+                RelationTarget::Contact(target) if !known_uids.contains(target.as_str()) => {
+                    continue
+                }
+                RelationTarget::Contact(target) => target.to_owned(),
+                RelationTarget::Person(name) => {
+                    // People not in the store are identified by their name
+                    let id = format!("person:{name}");
+                    if people.insert(id.clone()) {
+                        writeln!(
+                            writer,
+                            "    {} [label={}, style=dashed];",
+                            quote(&id),
+                            quote(name)
+                        )?;
+                    }
+                    id
+                }
+            };
+            writeln!(
+                writer,
+                "    {} -> {} [label={}];",
+                quote(uid),
+                quote(&target),
+                quote(relation.ty.name())
+            )?;
+        }
+    }
+    writeln!(writer, "}}")?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Formats a string as a quoted DOT identifier.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -4,59 +4,365 @@
 //! representation.
 
 use {
-    crate::{Address, Contact, Name, PartialDate, PhoneNumber, PhoneNumberType},
-    anyhow::Context,
+    crate::{
+        photo::validate_photo_file_name,
+        validate_extra_key, validate_language_tag, validate_pref, validate_tag, validate_uri,
+        vcard::{format_contentline, parse_contentline},
+        Address, AddressType, Contact, EmailAddress, EmailAddressType, Gender, Geo, ImppHandle,
+        ImppService, Name, Organization, PartialDate, PhoneNumber, PhoneNumberType, Relation,
+        RelationTarget, RelationType, Sex, SocialProfile, SocialService, Website, WebsiteType,
+    },
+    anyhow::{anyhow, bail, Context},
+    chrono_tz::Tz,
+    country_codes::CountryCode,
+    schemars::JsonSchema,
     serde::{Deserialize, Serialize},
-    std::io::{BufReader, Read, Write},
+    serde_json::value::RawValue,
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        fmt::{self, Display},
+        io::Write,
+        str::FromStr,
+    },
 };
 
 // ========================================================================== //
 // =====> structs to encode the structure of the JSON objects <============== //
 // ========================================================================== //
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 struct JsonContact {
+    /// A unique identifier of the contact as a URI, e.g. `urn:uuid:...`. If missing, one is
+    /// derived from the contact and written to the store the next time it is changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<String>,
     name: JsonName,
+    /// Birthday in the format `YYYY-MM-DD`, where every component may be left empty
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(regex(pattern = r"^[0-9]*-([0-9]{2})?-([0-9]{2})?$"))]
     bday: Option<String>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     phone: Vec<JsonPhoneNumber>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
-    email: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Vec<JsonEmailAddress>,
+    /// A single address. This is superseded by `addresses` and only kept for compatibility.
+    #[serde(default, skip_serializing)]
     address: Option<JsonAddress>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    addresses: Vec<JsonAddress>,
+    /// The name of the organization the contact works for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org: Option<String>,
+    /// The department within the organization
+    #[serde(skip_serializing_if = "Option::is_none")]
+    department: Option<String>,
+    /// The job title, e.g. "Research Scientist"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// The function within the organization, e.g. "Project Leader"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    /// Free-form notes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    /// Relations to other contacts or to people not in the store
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    relations: Vec<JsonRelation>,
+    /// The file name of a JPEG, PNG, GIF or WebP image in the `photos` directory of the store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    geo: Option<JsonGeo>,
+    /// The IANA time zone, e.g. "Europe/Zurich"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tz: Option<String>,
+    /// Language tags (BCP 47) of the languages the contact speaks, e.g. "de-CH", the most
+    /// preferred first
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    lang: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gender: Option<JsonGender>,
+    /// Websites, either as plain URLs or with a type
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    urls: Vec<JsonWebsite>,
+    /// Instant messaging handles
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    impp: Vec<JsonImppHandle>,
+    /// Profiles on social networks
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    social: Vec<JsonSocialProfile>,
+    /// Tags used to group contacts, e.g. "family"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Custom fields. The names may only contain ASCII letters, digits and '-'.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    extra: BTreeMap<String, String>,
+    /// Properties of imported vCards that tactful does not understand, as vCard content lines. They
+    /// are exported again verbatim.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    vcard_properties: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 struct JsonName {
+    /// Honorific prefixes, e.g. "Dr." or "Prof."
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prefixes: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     first: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    middle: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     last: String,
+    /// Honorific suffixes, e.g. "Jr." or "PhD"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    suffixes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nickname: Option<String>,
+    /// Overrides how the name is sorted, e.g. the phonetic reading of a name written in Kanji
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort_as: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 struct JsonPhoneNumber {
+    /// The phone number in international format, or in national format if the contact has an
+    /// address or a default region is configured
     number: String,
-    #[serde(rename = "type")]
-    ty: JsonPhoneNumberType,
+    /// The type of the phone number, or a list of types
+    #[serde(rename = "type", default)]
+    #[serde(skip_serializing_if = "JsonPhoneNumberTypes::is_empty")]
+    ty: JsonPhoneNumberTypes,
+    /// A free-form label, e.g. "Grandma's landline"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// The preference of this phone number, from 1 (most preferred) to 100 (least preferred)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 100))]
+    pref: Option<u8>,
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(untagged)]
+enum JsonPhoneNumberTypes {
+    Single(JsonPhoneNumberType),
+    Multiple(Vec<JsonPhoneNumberType>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
 #[serde(rename_all = "lowercase")]
 enum JsonPhoneNumberType {
     Mobile,
     Work,
     Home,
+    Fax,
+    Pager,
+    Main,
+    Voip,
+    Text,
+    Voice,
+    Video,
+    Textphone,
+}
+
+/// An email address, either as a plain string or with additional information
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(untagged)]
+enum JsonEmailAddress {
+    Plain(String),
+    Full {
+        address: String,
+        #[serde(rename = "type", default)]
+        ty: JsonEmailAddressType,
+        /// Whether this is the preferred email address of the contact
+        #[serde(default)]
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        pref: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonEmailAddressType {
+    Home,
+    Work,
+    #[default]
+    Other,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 struct JsonAddress {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     street: String,
+    /// The house number
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     number: String,
+    /// Apartment, suite, floor, etc.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extended: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    po_box: Option<String>,
     locality: String,
+    /// State, province, county, etc.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
     postal_code: String,
+    /// ISO 3166-1 alpha-2 country code
+    #[schemars(regex(pattern = "^[A-Z]{2}$"))]
     country: String,
+    #[serde(rename = "type", default)]
+    ty: JsonAddressType,
+    /// A free-form label, e.g. "Holiday home"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// The preference of this address, from 1 (most preferred) to 100 (least preferred)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1, max = 100))]
+    pref: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonAddressType {
+    Home,
+    Work,
+    #[default]
+    Other,
+}
+
+/// The gender of a contact. At least one of the fields must be given.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct JsonGender {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sex: Option<JsonSex>,
+    /// Free-form gender identity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonSex {
+    Male,
+    Female,
+    Other,
+    NotApplicable,
+    Unknown,
+}
+
+/// Geographic coordinates in degrees (WGS 84)
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct JsonGeo {
+    #[schemars(range(min = -90, max = 90))]
+    latitude: f64,
+    #[schemars(range(min = -180, max = 180))]
+    longitude: f64,
+}
+
+/// A website, either as a plain URL or with a type
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[serde(untagged)]
+enum JsonWebsite {
+    Plain(String),
+    Full {
+        url: String,
+        #[serde(rename = "type", default)]
+        ty: JsonWebsiteType,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonWebsiteType {
+    Home,
+    Work,
+    Blog,
+    #[default]
+    Other,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct JsonImppHandle {
+    service: JsonImppService,
+    /// The handle as usually written for the service, e.g. "@alice:example.org" for Matrix, a
+    /// phone number for Signal or a URI for other services
+    handle: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonImppService {
+    Matrix,
+    Signal,
+    Xmpp,
+    Telegram,
+    Other,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct JsonSocialProfile {
+    service: JsonSocialService,
+    /// The user name, e.g. "@alice@mastodon.social" for Mastodon, or the URL of the profile for
+    /// other services
+    handle: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonSocialService {
+    Mastodon,
+    Github,
+    Linkedin,
+    Twitter,
+    Other,
+}
+
+/// A relation to another person, given either by the UID of a contact or by a free-text name
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct JsonRelation {
+    #[serde(rename = "type")]
+    ty: JsonRelationType,
+    /// The UID of a contact in the store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<String>,
+    /// The name of a person that is not in the store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonRelationType {
+    Spouse,
+    Child,
+    Parent,
+    Sibling,
+    Kin,
+    Friend,
+    Colleague,
+    Neighbor,
+    Manager,
+    Assistant,
+    Emergency,
+    Other,
 }
 
 // ========================================================================== //
@@ -73,9 +379,29 @@ pub fn contacts_to_json<'a, C: Iterator<Item = &'a Contact>, W: Write>(
     )?)
 }
 
+/// Serializes a single contact to its compact JSON representation.
+#[cfg(feature = "sqlite")]
+pub fn contact_to_json(contact: &Contact) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&JsonContact::from(contact))?)
+}
+
+/// Like [`contacts_to_json`], but the output is indented to keep it easy to edit by hand.
+pub fn contacts_to_json_pretty<'a, C: Iterator<Item = &'a Contact>, W: Write>(
+    mut writer: W,
+    contacts: C,
+) -> anyhow::Result<()> {
+    serde_json::to_writer_pretty(
+        &mut writer,
+        &contacts.map(JsonContact::from).collect::<Vec<_>>(),
+    )?;
+    writeln!(writer)?;
+    Ok(())
+}
+
 impl From<&Contact> for JsonContact {
     fn from(contact: &Contact) -> Self {
         Self {
+            uid: Some(contact.uid.to_owned()),
             name: JsonName::from(&contact.name),
             bday: contact
                 .birthday
@@ -86,8 +412,49 @@ impl From<&Contact> for JsonContact {
                 .iter()
                 .map(JsonPhoneNumber::from)
                 .collect(),
-            email: contact.email_addresses.clone(),
-            address: contact.address.as_ref().map(JsonAddress::from),
+            email: contact
+                .email_addresses
+                .iter()
+                .map(JsonEmailAddress::from)
+                .collect(),
+            address: None,
+            addresses: contact.addresses.iter().map(JsonAddress::from).collect(),
+            org: contact
+                .organization
+                .as_ref()
+                .map(|organization| organization.name.to_owned()),
+            department: contact
+                .organization
+                .as_ref()
+                .and_then(|organization| organization.department.to_owned()),
+            title: contact.title.to_owned(),
+            role: contact.role.to_owned(),
+            notes: contact.notes.to_owned(),
+            relations: contact.relations.iter().map(JsonRelation::from).collect(),
+            photo: contact.photo.to_owned(),
+            geo: contact.geo.map(|geo| JsonGeo {
+                latitude: geo.latitude,
+                longitude: geo.longitude,
+            }),
+            tz: contact
+                .time_zone
+                .map(|time_zone| time_zone.name().to_owned()),
+            lang: contact.languages.clone(),
+            gender: contact.gender.as_ref().map(JsonGender::from),
+            urls: contact.websites.iter().map(JsonWebsite::from).collect(),
+            impp: contact.impp.iter().map(JsonImppHandle::from).collect(),
+            social: contact
+                .social_profiles
+                .iter()
+                .map(JsonSocialProfile::from)
+                .collect(),
+            tags: contact.tags.clone(),
+            extra: contact.extra.clone(),
+            vcard_properties: contact
+                .vcard_properties
+                .iter()
+                .map(format_contentline)
+                .collect(),
         }
     }
 }
@@ -95,8 +462,13 @@ impl From<&Contact> for JsonContact {
 impl From<&Name> for JsonName {
     fn from(name: &Name) -> Self {
         Self {
+            prefixes: name.prefixes.clone(),
             first: name.first.to_owned(),
+            middle: name.middle.clone(),
             last: name.last.to_owned(),
+            suffixes: name.suffixes.clone(),
+            nickname: name.nickname.clone(),
+            sort_as: name.sort_as.clone(),
         }
     }
 }
@@ -105,7 +477,24 @@ impl From<&PhoneNumber> for JsonPhoneNumber {
     fn from(phone_number: &PhoneNumber) -> Self {
         Self {
             number: phone_number.number.to_owned(),
-            ty: phone_number.ty.into(),
+            ty: JsonPhoneNumberTypes::from(phone_number.types.as_slice()),
+            label: phone_number.label.clone(),
+            pref: phone_number.pref,
+        }
+    }
+}
+
+impl From<&[PhoneNumberType]> for JsonPhoneNumberTypes {
+    fn from(phone_number_types: &[PhoneNumberType]) -> Self {
+        match phone_number_types {
+            [phone_number_type] => JsonPhoneNumberTypes::Single((*phone_number_type).into()),
+            phone_number_types => JsonPhoneNumberTypes::Multiple(
+                phone_number_types
+                    .iter()
+                    .copied()
+                    .map(JsonPhoneNumberType::from)
+                    .collect(),
+            ),
         }
     }
 }
@@ -116,6 +505,142 @@ impl From<PhoneNumberType> for JsonPhoneNumberType {
             PhoneNumberType::Mobile => JsonPhoneNumberType::Mobile,
             PhoneNumberType::Work => JsonPhoneNumberType::Work,
             PhoneNumberType::Home => JsonPhoneNumberType::Home,
+            PhoneNumberType::Fax => JsonPhoneNumberType::Fax,
+            PhoneNumberType::Pager => JsonPhoneNumberType::Pager,
+            PhoneNumberType::Main => JsonPhoneNumberType::Main,
+            PhoneNumberType::Voip => JsonPhoneNumberType::Voip,
+            PhoneNumberType::Text => JsonPhoneNumberType::Text,
+            PhoneNumberType::Voice => JsonPhoneNumberType::Voice,
+            PhoneNumberType::Video => JsonPhoneNumberType::Video,
+            PhoneNumberType::Textphone => JsonPhoneNumberType::Textphone,
+        }
+    }
+}
+
+impl From<&EmailAddress> for JsonEmailAddress {
+    fn from(email_address: &EmailAddress) -> Self {
+        // Use the more concise representation whenever possible
+        if email_address.ty == EmailAddressType::Other && !email_address.pref {
+            JsonEmailAddress::Plain(email_address.address.to_owned())
+        } else {
+            JsonEmailAddress::Full {
+                address: email_address.address.to_owned(),
+                ty: email_address.ty.into(),
+                pref: email_address.pref,
+            }
+        }
+    }
+}
+
+impl From<EmailAddressType> for JsonEmailAddressType {
+    fn from(email_address_type: EmailAddressType) -> Self {
+        match email_address_type {
+            EmailAddressType::Home => JsonEmailAddressType::Home,
+            EmailAddressType::Work => JsonEmailAddressType::Work,
+            EmailAddressType::Other => JsonEmailAddressType::Other,
+        }
+    }
+}
+
+impl From<&Gender> for JsonGender {
+    fn from(gender: &Gender) -> Self {
+        JsonGender {
+            sex: gender.sex.map(|sex| match sex {
+                Sex::Male => JsonSex::Male,
+                Sex::Female => JsonSex::Female,
+                Sex::Other => JsonSex::Other,
+                Sex::NotApplicable => JsonSex::NotApplicable,
+                Sex::Unknown => JsonSex::Unknown,
+            }),
+            identity: gender.identity.to_owned(),
+        }
+    }
+}
+
+impl From<&Website> for JsonWebsite {
+    fn from(website: &Website) -> Self {
+        // Use the more concise representation whenever possible
+        if website.ty == WebsiteType::Other {
+            JsonWebsite::Plain(website.url.to_owned())
+        } else {
+            JsonWebsite::Full {
+                url: website.url.to_owned(),
+                ty: website.ty.into(),
+            }
+        }
+    }
+}
+
+impl From<WebsiteType> for JsonWebsiteType {
+    fn from(website_type: WebsiteType) -> Self {
+        match website_type {
+            WebsiteType::Home => JsonWebsiteType::Home,
+            WebsiteType::Work => JsonWebsiteType::Work,
+            WebsiteType::Blog => JsonWebsiteType::Blog,
+            WebsiteType::Other => JsonWebsiteType::Other,
+        }
+    }
+}
+
+impl From<&ImppHandle> for JsonImppHandle {
+    fn from(impp_handle: &ImppHandle) -> Self {
+        JsonImppHandle {
+            service: match impp_handle.service {
+                ImppService::Matrix => JsonImppService::Matrix,
+                ImppService::Signal => JsonImppService::Signal,
+                ImppService::Xmpp => JsonImppService::Xmpp,
+                ImppService::Telegram => JsonImppService::Telegram,
+                ImppService::Other => JsonImppService::Other,
+            },
+            handle: impp_handle.handle.to_owned(),
+        }
+    }
+}
+
+impl From<&SocialProfile> for JsonSocialProfile {
+    fn from(social_profile: &SocialProfile) -> Self {
+        JsonSocialProfile {
+            service: match social_profile.service {
+                SocialService::Mastodon => JsonSocialService::Mastodon,
+                SocialService::Github => JsonSocialService::Github,
+                SocialService::Linkedin => JsonSocialService::Linkedin,
+                SocialService::Twitter => JsonSocialService::Twitter,
+                SocialService::Other => JsonSocialService::Other,
+            },
+            handle: social_profile.handle.to_owned(),
+        }
+    }
+}
+
+impl From<&Relation> for JsonRelation {
+    fn from(relation: &Relation) -> Self {
+        let (uid, name) = match &relation.target {
+            RelationTarget::Contact(uid) => (Some(uid.to_owned()), None),
+            RelationTarget::Person(name) => (None, Some(name.to_owned())),
+        };
+        JsonRelation {
+            ty: relation.ty.into(),
+            uid,
+            name,
+        }
+    }
+}
+
+impl From<RelationType> for JsonRelationType {
+    fn from(relation_type: RelationType) -> Self {
+        match relation_type {
+            RelationType::Spouse => JsonRelationType::Spouse,
+            RelationType::Child => JsonRelationType::Child,
+            RelationType::Parent => JsonRelationType::Parent,
+            RelationType::Sibling => JsonRelationType::Sibling,
+            RelationType::Kin => JsonRelationType::Kin,
+            RelationType::Friend => JsonRelationType::Friend,
+            RelationType::Colleague => JsonRelationType::Colleague,
+            RelationType::Neighbor => JsonRelationType::Neighbor,
+            RelationType::Manager => JsonRelationType::Manager,
+            RelationType::Assistant => JsonRelationType::Assistant,
+            RelationType::Emergency => JsonRelationType::Emergency,
+            RelationType::Other => JsonRelationType::Other,
         }
     }
 }
@@ -125,55 +650,536 @@ impl From<&Address> for JsonAddress {
         Self {
             street: address.street.to_owned(),
             number: address.number.to_owned(),
+            extended: address.extended.clone(),
+            po_box: address.po_box.clone(),
             locality: address.locality.to_owned(),
+            region: address.region.clone(),
             postal_code: address.postal_code.to_owned(),
             country: address.country.alpha2.to_owned(),
+            ty: address.ty.into(),
+            label: address.label.clone(),
+            pref: address.pref,
+        }
+    }
+}
+
+impl From<AddressType> for JsonAddressType {
+    fn from(address_type: AddressType) -> Self {
+        match address_type {
+            AddressType::Home => JsonAddressType::Home,
+            AddressType::Work => JsonAddressType::Work,
+            AddressType::Other => JsonAddressType::Other,
         }
     }
 }
 
+// ========================================================================== //
+// =====> JSON schema <====================================================== //
+// ========================================================================== //
+
+/// Writes a JSON schema describing the format of the contact store
+pub fn contacts_json_schema<W: Write>(writer: W) -> anyhow::Result<()> {
+    let schema = schemars::schema_for!(Vec<JsonContact>);
+    Ok(serde_json::to_writer_pretty(writer, &schema)?)
+}
+
 // ========================================================================== //
 // =====> deserialization <================================================== //
 // ========================================================================== //
 
-pub fn contacts_from_json<R: Read>(reader: R) -> anyhow::Result<Vec<Contact>> {
-    let json_contacts: Vec<JsonContact> = serde_json::from_reader(BufReader::new(reader))?;
-    json_contacts
-        .into_iter()
-        .map(Contact::try_from)
-        .collect::<anyhow::Result<Vec<_>>>()
-        .context("Failed to parse contact store")
+/// Deserializes all valid contacts from their JSON representation, skipping invalid ones.
+///
+/// Diagnostics for all the problems encountered are returned along with the valid contacts.
+/// Contacts using the UID of a previous contact are invalid as well.
+///
+/// Contacts without a UID are given one derived from their position and content, see
+/// [`Contact::derived_uid`].
+///
+/// Phone numbers in national format are resolved against `default_region` if the contact has no
+/// addresses.
+pub fn contacts_from_json_lenient(
+    input: &str,
+    default_region: Option<CountryCode>,
+) -> (Vec<Contact>, Vec<Diagnostic>) {
+    let elements: Vec<&RawValue> = match serde_json::from_str(input) {
+        Ok(elements) => elements,
+        Err(error) => {
+            let diagnostic = Diagnostic {
+                path: JsonPath::default(),
+                contact: None,
+                line: error.line(),
+                column: error.column(),
+                error: without_position(error),
+            };
+            return (Vec::new(), vec![diagnostic]);
+        }
+    };
+
+    let mut contacts = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut uids = HashSet::new();
+    for (index, element) in elements.into_iter().enumerate() {
+        let contact_path = JsonPath::default().index(index);
+        match contact_from_element(input, element, contact_path.clone(), default_region) {
+            Ok(contact) if uids.insert(contact.uid.clone()) => contacts.push(contact),
+            Ok(contact) => {
+                let uid_path = JsonPath::default().key("uid");
+                let (line, column) = line_column(input, uid_path.locate(element));
+                diagnostics.push(Diagnostic {
+                    path: contact_path.join(&uid_path),
+                    contact: Some(contact.name.to_string()),
+                    line,
+                    column,
+                    error: anyhow!(
+                        "The UID \"{}\" is already used by another contact",
+                        contact.uid
+                    ),
+                });
+            }
+            Err(contact_diagnostics) => diagnostics.extend(contact_diagnostics),
+        }
+    }
+
+    (contacts, diagnostics)
 }
 
-impl TryFrom<JsonContact> for Contact {
-    type Error = anyhow::Error;
-    fn try_from(json_contact: JsonContact) -> anyhow::Result<Self> {
-        let error_message = || {
-            format!(
-                "Failed to parse contact \"{} {}\"",
-                &json_contact.name.first, &json_contact.name.last
+/// Deserializes a single contact from its JSON representation.
+#[cfg(feature = "sqlite")]
+pub fn contact_from_json(
+    input: &str,
+    default_region: Option<CountryCode>,
+) -> Result<Contact, Vec<Diagnostic>> {
+    let element: &RawValue = serde_json::from_str(input).map_err(|error| {
+        vec![Diagnostic {
+            path: JsonPath::default(),
+            contact: None,
+            line: error.line(),
+            column: error.column(),
+            error: without_position(error),
+        }]
+    })?;
+    contact_from_element(input, element, JsonPath::default(), default_region)
+}
+
+/// Deserializes the contact `element`, which is located at `contact_path` in `input`.
+fn contact_from_element(
+    input: &str,
+    element: &RawValue,
+    contact_path: JsonPath,
+    default_region: Option<CountryCode>,
+) -> Result<Contact, Vec<Diagnostic>> {
+    match serde_json::from_str::<JsonContact>(element.get()) {
+        Ok(mut json_contact) => {
+            let name = Name::from(&json_contact.name).to_string();
+            json_contact.uid.get_or_insert_with(|| {
+                Contact::derived_uid(&format!("{contact_path}{}", element.get()))
+            });
+            json_contact
+                .into_contact(default_region)
+                .map_err(|problems| {
+                    problems
+                        .into_iter()
+                        .map(|problem| {
+                            let (line, column) = line_column(input, problem.path.locate(element));
+                            Diagnostic {
+                                path: contact_path.clone().join(&problem.path),
+                                contact: Some(name.clone()),
+                                line,
+                                column,
+                                error: problem.error,
+                            }
+                        })
+                        .collect()
+                })
+        }
+        Err(error) => {
+            // serde_json reports the position relative to the start of the contact
+            let (line, column) = line_column(input, element);
+            let (line, column) = if error.line() <= 1 {
+                (line, column + error.column().saturating_sub(1))
+            } else {
+                (line + error.line() - 1, error.column())
+            };
+            Err(vec![Diagnostic {
+                path: contact_path,
+                contact: None,
+                line,
+                column,
+                error: without_position(error),
+            }])
+        }
+    }
+}
+
+/// Computes the line and column (both starting at 1) at which `value` starts in `input`.
+///
+/// `value` must have been deserialized from `input`.
+fn line_column(input: &str, value: &RawValue) -> (usize, usize) {
+    let offset = value.get().as_ptr() as usize - input.as_ptr() as usize;
+    let preceding = &input[..offset];
+    let line = preceding.matches('\n').count() + 1;
+    let column = preceding.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+/// Strips the position from the message of a [`serde_json::Error`].
+///
+/// This is useful when the position is reported separately.
+fn without_position(error: serde_json::Error) -> anyhow::Error {
+    let message = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+    anyhow::Error::msg(
+        message
+            .strip_suffix(&position)
+            .unwrap_or(&message)
+            .to_owned(),
+    )
+}
+
+/// A problem found in the JSON representation of the contacts, along with its location
+#[derive(Debug)]
+pub struct Diagnostic {
+    path: JsonPath,
+    /// The name of the contact the problem was found in, if it is known
+    contact: Option<String>,
+    line: usize,
+    column: usize,
+    error: anyhow::Error,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.path)?;
+        if let Some(contact) = &self.contact {
+            write!(f, " (contact \"{contact}\")")?;
+        }
+        write!(f, ": {:#}", self.error)
+    }
+}
+
+/// A problem with a single value of a contact
+#[derive(Debug)]
+struct Problem {
+    /// The path of the offending value, relative to the contact
+    path: JsonPath,
+    error: anyhow::Error,
+}
+
+impl Problem {
+    /// Records the error of `result` (if any) as a problem of the value at `path`.
+    fn collect<T>(
+        problems: &mut Vec<Problem>,
+        path: JsonPath,
+        result: anyhow::Result<T>,
+    ) -> Option<T> {
+        result
+            .map_err(|error| problems.push(Problem { path, error }))
+            .ok()
+    }
+}
+
+/// The path to a value in a JSON document, e.g. `$[3].phone[1]`
+#[derive(Clone, Debug, Default)]
+struct JsonPath(Vec<JsonPathSegment>);
+
+#[derive(Clone, Copy, Debug)]
+enum JsonPathSegment {
+    Key(&'static str),
+    Index(usize),
+}
+
+impl JsonPath {
+    fn key(mut self, key: &'static str) -> Self {
+        self.0.push(JsonPathSegment::Key(key));
+        self
+    }
+
+    fn index(mut self, index: usize) -> Self {
+        self.0.push(JsonPathSegment::Index(index));
+        self
+    }
+
+    fn join(mut self, other: &JsonPath) -> Self {
+        self.0.extend_from_slice(&other.0);
+        self
+    }
+
+    /// Finds the value this path points to, starting from `value`.
+    ///
+    /// If the path cannot be followed all the way, the deepest value that could be found is
+    /// returned instead.
+    fn locate<'a>(&self, value: &'a RawValue) -> &'a RawValue {
+        let mut value = value;
+        for segment in &self.0 {
+            let child = match *segment {
+                JsonPathSegment::Key(key) => {
+                    serde_json::from_str::<HashMap<String, &RawValue>>(value.get())
+                        .ok()
+                        .and_then(|mut object| object.remove(key))
+                }
+                JsonPathSegment::Index(index) => {
+                    serde_json::from_str::<Vec<&RawValue>>(value.get())
+                        .ok()
+                        .and_then(|array| array.get(index).copied())
+                }
+            };
+            match child {
+                Some(child) => value = child,
+                None => break,
+            }
+        }
+        value
+    }
+}
+
+impl Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$")?;
+        for segment in &self.0 {
+            match segment {
+                JsonPathSegment::Key(key) => write!(f, ".{key}")?,
+                JsonPathSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JsonContact {
+    /// Converts this JSON contact to a [`Contact`].
+    ///
+    /// If the contact is invalid, all of its problems are returned, not only the first one.
+    fn into_contact(self, default_region: Option<CountryCode>) -> Result<Contact, Vec<Problem>> {
+        let mut problems = Vec::new();
+
+        let legacy_address = self.address.and_then(|address| {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("address"),
+                Address::try_from(address),
+            )
+        });
+        let addresses = legacy_address
+            .into_iter()
+            .chain(
+                self.addresses
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(index, address)| {
+                        Problem::collect(
+                            &mut problems,
+                            JsonPath::default().key("addresses").index(index),
+                            Address::try_from(address),
+                        )
+                    }),
+            )
+            .collect::<Vec<_>>();
+        // Phone numbers in national format belong to the country the contact lives in
+        let region = addresses
+            .iter()
+            .min_by_key(|address| address.pref.unwrap_or(u8::MAX))
+            .map(|address| address.country)
+            .or(default_region);
+
+        let birthday = self.bday.and_then(|date| {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("bday"),
+                PartialDate::from_json_string_repr(&date),
             )
+        });
+        let phone_numbers = self
+            .phone
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, phone_number)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("phone").index(index),
+                    phone_number.into_phone_number(region),
+                )
+            })
+            .collect();
+        let email_addresses = self
+            .email
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, email_address)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("email").index(index),
+                    EmailAddress::try_from(email_address),
+                )
+            })
+            .collect();
+
+        let name = Name::from(&self.name);
+        Problem::collect(
+            &mut problems,
+            JsonPath::default().key("name"),
+            name.validate(),
+        );
+        let organization = match (self.org, self.department) {
+            (Some(name), department) => Some(Organization { name, department }),
+            (None, None) => None,
+            (None, Some(_)) => {
+                problems.push(Problem {
+                    path: JsonPath::default().key("department"),
+                    error: anyhow!("A department requires an organization (\"org\")"),
+                });
+                None
+            }
         };
 
+        if let Some(uid) = &self.uid {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("uid"),
+                validate_uri(uid).context("Invalid UID"),
+            );
+        }
+        let geo = self.geo.and_then(|geo| {
+            let geo = Geo {
+                latitude: geo.latitude,
+                longitude: geo.longitude,
+            };
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("geo"),
+                geo.validate(),
+            )
+            .map(|()| geo)
+        });
+        let time_zone = self.tz.and_then(|time_zone| {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("tz"),
+                Tz::from_str(&time_zone).map_err(|_| anyhow!("Unknown time zone \"{time_zone}\"")),
+            )
+        });
+        for (index, language) in self.lang.iter().enumerate() {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("lang").index(index),
+                validate_language_tag(language),
+            );
+        }
+        let gender = self.gender.and_then(|gender| {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("gender"),
+                Gender::try_from(gender),
+            )
+        });
+        let websites = self
+            .urls
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, website)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("urls").index(index),
+                    Website::try_from(website),
+                )
+            })
+            .collect();
+        let impp = self
+            .impp
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, impp_handle)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("impp").index(index),
+                    ImppHandle::try_from(impp_handle),
+                )
+            })
+            .collect();
+        let social_profiles = self
+            .social
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, social_profile)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("social").index(index),
+                    SocialProfile::try_from(social_profile),
+                )
+            })
+            .collect();
+        let relations = self
+            .relations
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, relation)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("relations").index(index),
+                    Relation::try_from(relation),
+                )
+            })
+            .collect();
+        let vcard_properties = self
+            .vcard_properties
+            .iter()
+            .enumerate()
+            .filter_map(|(index, contentline)| {
+                Problem::collect(
+                    &mut problems,
+                    JsonPath::default().key("vcard_properties").index(index),
+                    parse_contentline(contentline),
+                )
+            })
+            .collect();
+        if let Some(photo) = &self.photo {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("photo"),
+                validate_photo_file_name(photo),
+            );
+        }
+        for (index, tag) in self.tags.iter().enumerate() {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("tags").index(index),
+                validate_tag(tag),
+            );
+        }
+        for key in self.extra.keys() {
+            Problem::collect(
+                &mut problems,
+                JsonPath::default().key("extra"),
+                validate_extra_key(key),
+            );
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
         Ok(Contact {
-            name: Name::from(&json_contact.name),
-            birthday: json_contact
-                .bday
-                .map(|date| PartialDate::from_json_string_repr(&date))
-                .transpose()
-                .with_context(error_message)?,
-            phone_numbers: json_contact
-                .phone
-                .into_iter()
-                .map(PhoneNumber::try_from)
-                .collect::<Result<Vec<_>, _>>()
-                .with_context(error_message)?,
-            email_addresses: json_contact.email,
-            address: json_contact
-                .address
-                .map(Address::try_from)
-                .transpose()
-                .with_context(error_message)?,
+            uid: self.uid.unwrap_or_else(Contact::new_uid),
+            name,
+            birthday,
+            phone_numbers,
+            email_addresses,
+            addresses,
+            organization,
+            title: self.title,
+            role: self.role,
+            notes: self.notes,
+            relations,
+            photo: self.photo,
+            geo,
+            time_zone,
+            languages: self.lang,
+            gender,
+            websites,
+            impp,
+            social_profiles,
+            tags: self.tags,
+            extra: self.extra,
+            vcard_properties,
         })
     }
 }
@@ -181,25 +1187,39 @@ impl TryFrom<JsonContact> for Contact {
 impl From<&JsonName> for Name {
     fn from(json_name: &JsonName) -> Self {
         Name {
+            prefixes: json_name.prefixes.clone(),
             first: json_name.first.to_owned(),
+            middle: json_name.middle.clone(),
             last: json_name.last.to_owned(),
+            suffixes: json_name.suffixes.clone(),
+            nickname: json_name.nickname.clone(),
+            sort_as: json_name.sort_as.clone(),
         }
     }
 }
 
-impl TryFrom<JsonPhoneNumber> for PhoneNumber {
-    type Error = anyhow::Error;
-    fn try_from(json_phone_number: JsonPhoneNumber) -> anyhow::Result<Self> {
-        let phone_number = PhoneNumber {
-            number: json_phone_number.number,
-            ty: PhoneNumberType::from(json_phone_number.ty),
+impl JsonPhoneNumber {
+    fn into_phone_number(self, region: Option<CountryCode>) -> anyhow::Result<PhoneNumber> {
+        let types = match self.ty {
+            JsonPhoneNumberTypes::Single(ty) => vec![PhoneNumberType::from(ty)],
+            JsonPhoneNumberTypes::Multiple(types) => {
+                types.into_iter().map(PhoneNumberType::from).collect()
+            }
         };
+        PhoneNumber::parse(self.number, types, self.label, self.pref, region)
+            .context("Failed to parse phone number")
+    }
+}
 
-        phone_number
-            .validate()
-            .context("Failed to parse phone number")?;
+impl JsonPhoneNumberTypes {
+    fn is_empty(&self) -> bool {
+        matches!(self, JsonPhoneNumberTypes::Multiple(types) if types.is_empty())
+    }
+}
 
-        Ok(phone_number)
+impl Default for JsonPhoneNumberTypes {
+    fn default() -> Self {
+        JsonPhoneNumberTypes::Multiple(Vec::new())
     }
 }
 
@@ -209,6 +1229,169 @@ impl From<JsonPhoneNumberType> for PhoneNumberType {
             JsonPhoneNumberType::Mobile => PhoneNumberType::Mobile,
             JsonPhoneNumberType::Work => PhoneNumberType::Work,
             JsonPhoneNumberType::Home => PhoneNumberType::Home,
+            JsonPhoneNumberType::Fax => PhoneNumberType::Fax,
+            JsonPhoneNumberType::Pager => PhoneNumberType::Pager,
+            JsonPhoneNumberType::Main => PhoneNumberType::Main,
+            JsonPhoneNumberType::Voip => PhoneNumberType::Voip,
+            JsonPhoneNumberType::Text => PhoneNumberType::Text,
+            JsonPhoneNumberType::Voice => PhoneNumberType::Voice,
+            JsonPhoneNumberType::Video => PhoneNumberType::Video,
+            JsonPhoneNumberType::Textphone => PhoneNumberType::Textphone,
+        }
+    }
+}
+
+impl TryFrom<JsonGender> for Gender {
+    type Error = anyhow::Error;
+    fn try_from(json_gender: JsonGender) -> anyhow::Result<Self> {
+        if json_gender.sex.is_none() && json_gender.identity.is_none() {
+            bail!("Gender must contain a sex or an identity");
+        }
+        Ok(Gender {
+            sex: json_gender.sex.map(|sex| match sex {
+                JsonSex::Male => Sex::Male,
+                JsonSex::Female => Sex::Female,
+                JsonSex::Other => Sex::Other,
+                JsonSex::NotApplicable => Sex::NotApplicable,
+                JsonSex::Unknown => Sex::Unknown,
+            }),
+            identity: json_gender.identity,
+        })
+    }
+}
+
+impl TryFrom<JsonWebsite> for Website {
+    type Error = anyhow::Error;
+    fn try_from(json_website: JsonWebsite) -> anyhow::Result<Self> {
+        let website = match json_website {
+            JsonWebsite::Plain(url) => Website {
+                url,
+                ty: WebsiteType::Other,
+            },
+            JsonWebsite::Full { url, ty } => Website {
+                url,
+                ty: match ty {
+                    JsonWebsiteType::Home => WebsiteType::Home,
+                    JsonWebsiteType::Work => WebsiteType::Work,
+                    JsonWebsiteType::Blog => WebsiteType::Blog,
+                    JsonWebsiteType::Other => WebsiteType::Other,
+                },
+            },
+        };
+        validate_uri(&website.url).context("Failed to parse URL")?;
+        Ok(website)
+    }
+}
+
+impl TryFrom<JsonImppHandle> for ImppHandle {
+    type Error = anyhow::Error;
+    fn try_from(json_impp_handle: JsonImppHandle) -> anyhow::Result<Self> {
+        let impp_handle = ImppHandle {
+            service: match json_impp_handle.service {
+                JsonImppService::Matrix => ImppService::Matrix,
+                JsonImppService::Signal => ImppService::Signal,
+                JsonImppService::Xmpp => ImppService::Xmpp,
+                JsonImppService::Telegram => ImppService::Telegram,
+                JsonImppService::Other => ImppService::Other,
+            },
+            handle: json_impp_handle.handle,
+        };
+        impp_handle
+            .validate()
+            .context("Failed to parse instant messaging handle")?;
+        Ok(impp_handle)
+    }
+}
+
+impl TryFrom<JsonSocialProfile> for SocialProfile {
+    type Error = anyhow::Error;
+    fn try_from(json_social_profile: JsonSocialProfile) -> anyhow::Result<Self> {
+        let social_profile = SocialProfile {
+            service: match json_social_profile.service {
+                JsonSocialService::Mastodon => SocialService::Mastodon,
+                JsonSocialService::Github => SocialService::Github,
+                JsonSocialService::Linkedin => SocialService::Linkedin,
+                JsonSocialService::Twitter => SocialService::Twitter,
+                JsonSocialService::Other => SocialService::Other,
+            },
+            handle: json_social_profile.handle,
+        };
+        social_profile
+            .validate()
+            .context("Failed to parse social profile")?;
+        Ok(social_profile)
+    }
+}
+
+impl TryFrom<JsonRelation> for Relation {
+    type Error = anyhow::Error;
+    fn try_from(json_relation: JsonRelation) -> anyhow::Result<Self> {
+        let target = match (json_relation.uid, json_relation.name) {
+            (Some(uid), None) => {
+                validate_uri(&uid).context("Invalid UID")?;
+                RelationTarget::Contact(uid)
+            }
+            (None, Some(name)) => RelationTarget::Person(name),
+            _ => {
+                bail!("A relation must reference either a contact (\"uid\") or a person (\"name\")")
+            }
+        };
+        Ok(Relation {
+            ty: json_relation.ty.into(),
+            target,
+        })
+    }
+}
+
+impl From<JsonRelationType> for RelationType {
+    fn from(json_relation_type: JsonRelationType) -> Self {
+        match json_relation_type {
+            JsonRelationType::Spouse => RelationType::Spouse,
+            JsonRelationType::Child => RelationType::Child,
+            JsonRelationType::Parent => RelationType::Parent,
+            JsonRelationType::Sibling => RelationType::Sibling,
+            JsonRelationType::Kin => RelationType::Kin,
+            JsonRelationType::Friend => RelationType::Friend,
+            JsonRelationType::Colleague => RelationType::Colleague,
+            JsonRelationType::Neighbor => RelationType::Neighbor,
+            JsonRelationType::Manager => RelationType::Manager,
+            JsonRelationType::Assistant => RelationType::Assistant,
+            JsonRelationType::Emergency => RelationType::Emergency,
+            JsonRelationType::Other => RelationType::Other,
+        }
+    }
+}
+
+impl TryFrom<JsonEmailAddress> for EmailAddress {
+    type Error = anyhow::Error;
+    fn try_from(json_email_address: JsonEmailAddress) -> anyhow::Result<Self> {
+        let email_address = match json_email_address {
+            JsonEmailAddress::Plain(address) => EmailAddress {
+                address,
+                ty: EmailAddressType::Other,
+                pref: false,
+            },
+            JsonEmailAddress::Full { address, ty, pref } => EmailAddress {
+                address,
+                ty: EmailAddressType::from(ty),
+                pref,
+            },
+        };
+
+        email_address
+            .validate()
+            .context("Failed to parse email address")?;
+
+        Ok(email_address)
+    }
+}
+
+impl From<JsonEmailAddressType> for EmailAddressType {
+    fn from(json_email_address_type: JsonEmailAddressType) -> Self {
+        match json_email_address_type {
+            JsonEmailAddressType::Home => EmailAddressType::Home,
+            JsonEmailAddressType::Work => EmailAddressType::Work,
+            JsonEmailAddressType::Other => EmailAddressType::Other,
         }
     }
 }
@@ -216,13 +1399,81 @@ impl From<JsonPhoneNumberType> for PhoneNumberType {
 impl TryFrom<JsonAddress> for Address {
     type Error = anyhow::Error;
     fn try_from(json_address: JsonAddress) -> anyhow::Result<Self> {
-        Ok(Address {
+        let address = Address {
             street: json_address.street,
             number: json_address.number,
+            extended: json_address.extended,
+            po_box: json_address.po_box,
             locality: json_address.locality,
+            region: json_address.region,
             postal_code: json_address.postal_code,
             country: country_codes::from_alpha2(&json_address.country)
                 .context("Failed to parse address")?,
-        })
+            ty: AddressType::from(json_address.ty),
+            label: json_address.label,
+            pref: json_address.pref,
+        };
+
+        validate_pref(address.pref).context("Failed to parse address")?;
+
+        Ok(address)
+    }
+}
+
+impl From<JsonAddressType> for AddressType {
+    fn from(json_address_type: JsonAddressType) -> Self {
+        match json_address_type {
+            JsonAddressType::Home => AddressType::Home,
+            JsonAddressType::Work => AddressType::Work,
+            JsonAddressType::Other => AddressType::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics(input: &str) -> Vec<String> {
+        let (_, diagnostics) = contacts_from_json_lenient(input, None);
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn invalid_values_are_reported_with_their_path() {
+        let input = r#"[
+  {"name": {"first": "Anna"}},
+  {
+    "name": {"first": "Bob"},
+    "email": [{"address": "bob@example.org"}, {"address": "bob"}],
+    "bday": "1980-13-01"
+  }
+]"#;
+        let (contacts, _) = contacts_from_json_lenient(input, None);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(
+            diagnostics(input),
+            [
+                "6:13: $[1].bday (contact \"Bob\"): Invalid date \"1980-13-01\": Invalid month: 13",
+                "5:47: $[1].email[1] (contact \"Bob\"): Failed to parse email address: \
+                 Email address must contain an '@'",
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_with_their_line_and_column() {
+        let input = "[\n  {\"name\": {\"first\": \"Anna\"}},\n  {\"name\": }\n]";
+        assert_eq!(diagnostics(input), ["3:12: $: expected value"]);
+
+        // Errors within a contact point to the offending value rather than the start of the file
+        let input = "[\n  {\"name\": {\"first\": \"Anna\"}},\n  {\"name\": {\"first\": 1}}\n]";
+        assert_eq!(
+            diagnostics(input),
+            ["3:22: $[1]: invalid type: integer `1`, expected a string"]
+        );
     }
 }
@@ -0,0 +1,309 @@
+//! Mailing labels
+//!
+//! This module renders the addresses of contacts as plain text, as CSV for mail merge, or as label
+//! sheets in SVG or PDF format. The label sheets follow the layouts of common Avery products.
+
+use {
+    crate::{
+        args::{LabelFormat, LabelSheet},
+        Address,
+    },
+    std::io::Write,
+};
+
+/// A single mailing label
+#[derive(Clone, Debug)]
+pub struct Label<'a> {
+    pub recipient: String,
+    pub address: &'a Address,
+    /// The lines of the label, starting with the recipient
+    pub lines: Vec<String>,
+}
+
+pub fn write_labels<W: Write>(
+    mut writer: W,
+    labels: &[Label],
+    format: LabelFormat,
+    sheet: LabelSheet,
+) -> anyhow::Result<()> {
+    match format {
+        LabelFormat::Text => write_text(&mut writer, labels)?,
+        LabelFormat::Csv => write_csv(&mut writer, labels)?,
+        LabelFormat::Svg => write_svg(&mut writer, labels, SheetLayout::of(sheet))?,
+        LabelFormat::Pdf => write_pdf(&mut writer, labels, SheetLayout::of(sheet))?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_text<W: Write>(writer: &mut W, labels: &[Label]) -> anyhow::Result<()> {
+    for (index, label) in labels.iter().enumerate() {
+        if index > 0 {
+            writeln!(writer)?;
+        }
+        for line in &label.lines {
+            writeln!(writer, "{line}")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(writer: &mut W, labels: &[Label]) -> anyhow::Result<()> {
+    // Lines are terminated by CRLF, as mandated by RFC 4180
+    write!(
+        writer,
+        "name,street,extended,po_box,locality,region,postal_code,country,label\r\n"
+    )?;
+    for label in labels {
+        let address = label.address;
+        let fields = [
+            label.recipient.as_str(),
+            &address.street_line(),
+            address.extended.as_deref().unwrap_or_default(),
+            address.po_box.as_deref().unwrap_or_default(),
+            &address.locality,
+            address.region.as_deref().unwrap_or_default(),
+            &address.postal_code,
+            address.country.name,
+            &label.lines.join("\n"),
+        ];
+        let fields = fields.map(escape_csv_field);
+        write!(writer, "{}\r\n", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if necessary (see RFC 4180).
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// ========================================================================== //
+// =====> label sheets <===================================================== //
+// ========================================================================== //
+
+/// The font size of the text on the labels, in points
+const FONT_SIZE: f64 = 9.0;
+/// The distance between the baselines of two lines, in millimeters
+const LINE_HEIGHT: f64 = 3.9;
+/// The distance between the edge of a label and its text, in millimeters
+const PADDING: f64 = 3.0;
+
+const MM_PER_POINT: f64 = 25.4 / 72.0;
+
+/// The layout of a label sheet. All lengths are in millimeters.
+#[derive(Clone, Copy, Debug)]
+struct SheetLayout {
+    page_width: f64,
+    page_height: f64,
+    columns: usize,
+    rows: usize,
+    /// The distance between the left edge of the page and the first column
+    left_margin: f64,
+    /// The distance between the top edge of the page and the first row
+    top_margin: f64,
+    /// The distance between the left edges of two adjacent labels
+    horizontal_pitch: f64,
+    /// The distance between the top edges of two adjacent labels
+    vertical_pitch: f64,
+}
+
+impl SheetLayout {
+    fn of(sheet: LabelSheet) -> Self {
+        match sheet {
+            // A4, 3 x 7 labels of 63.5mm x 38.1mm
+            LabelSheet::AveryL7160 => Self {
+                page_width: 210.0,
+                page_height: 297.0,
+                columns: 3,
+                rows: 7,
+                left_margin: 7.25,
+                top_margin: 15.15,
+                horizontal_pitch: 66.04,
+                vertical_pitch: 38.1,
+            },
+            // US Letter, 3 x 10 labels of 2 5/8" x 1"
+            LabelSheet::Avery5160 => Self {
+                page_width: 215.9,
+                page_height: 279.4,
+                columns: 3,
+                rows: 10,
+                left_margin: 4.7625,
+                top_margin: 12.7,
+                horizontal_pitch: 69.85,
+                vertical_pitch: 25.4,
+            },
+        }
+    }
+
+    fn labels_per_page(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// The position of the top left corner of the label with the given index on its page
+    fn position(&self, index: usize) -> (f64, f64) {
+        let index = index % self.labels_per_page();
+        let column = index % self.columns;
+        let row = index / self.columns;
+        (
+            self.left_margin + column as f64 * self.horizontal_pitch,
+            self.top_margin + row as f64 * self.vertical_pitch,
+        )
+    }
+}
+
+/// Writes the labels as a single SVG image.
+///
+/// If the labels do not fit onto a single sheet, the sheets are stacked vertically.
+fn write_svg<W: Write>(
+    writer: &mut W,
+    labels: &[Label],
+    layout: SheetLayout,
+) -> anyhow::Result<()> {
+    let pages = labels.len().div_ceil(layout.labels_per_page()).max(1);
+    let height = pages as f64 * layout.page_height;
+
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}mm" height="{height}mm" viewBox="0 0 {width} {height}" font-family="Helvetica, Arial, sans-serif" font-size="{font_size}">"#,
+        width = layout.page_width,
+        font_size = FONT_SIZE * MM_PER_POINT,
+    )?;
+    for (index, label) in labels.iter().enumerate() {
+        let page = index / layout.labels_per_page();
+        let (x, y) = layout.position(index);
+        let y = y + page as f64 * layout.page_height;
+        writeln!(writer, "  <text>")?;
+        for (line_index, line) in label.lines.iter().enumerate() {
+            writeln!(
+                writer,
+                r#"    <tspan x="{x:.2}" y="{y:.2}">{line}</tspan>"#,
+                x = x + PADDING,
+                y = y + PADDING + (line_index + 1) as f64 * LINE_HEIGHT,
+                line = escape_xml(line),
+            )?;
+        }
+        writeln!(writer, "  </text>")?;
+    }
+    writeln!(writer, "</svg>")?;
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the labels as a PDF document with one page per sheet.
+///
+/// The text is set in Helvetica, one of the standard fonts every PDF reader provides, so no fonts
+/// need to be embedded. Characters that are not part of the Windows-1252 character set cannot be
+/// represented and are replaced by question marks.
+fn write_pdf<W: Write>(
+    writer: &mut W,
+    labels: &[Label],
+    layout: SheetLayout,
+) -> anyhow::Result<()> {
+    let to_points = |mm: f64| mm / MM_PER_POINT;
+
+    let pages = labels
+        .chunks(layout.labels_per_page())
+        .map(|page_labels| {
+            let mut content = Vec::new();
+            for (index, label) in page_labels.iter().enumerate() {
+                let (x, y) = layout.position(index);
+                for (line_index, line) in label.lines.iter().enumerate() {
+                    let baseline = y + PADDING + (line_index + 1) as f64 * LINE_HEIGHT;
+                    content.extend_from_slice(
+                        format!(
+                            "BT /F1 {FONT_SIZE} Tf {:.2} {:.2} Td (",
+                            to_points(x + PADDING),
+                            to_points(layout.page_height - baseline),
+                        )
+                        .as_bytes(),
+                    );
+                    content.extend(encode_pdf_string(line));
+                    content.extend_from_slice(b") Tj ET\n");
+                }
+            }
+            content
+        })
+        .collect::<Vec<_>>();
+
+    // Objects 1 to 3 are the catalog, the page tree and the font. They are followed by a page
+    // object and a content stream for every page.
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|page| format!("{} 0 R", 4 + 2 * page))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len(),
+        )
+        .into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_vec(),
+    ];
+    for (page, content) in pages.into_iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                to_points(layout.page_width),
+                to_points(layout.page_height),
+                5 + 2 * page,
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", index + 1).into_bytes());
+        pdf.extend(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref_offset = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{offset:010} 00000 n \n").into_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .into_bytes(),
+    );
+
+    writer.write_all(&pdf)?;
+    Ok(())
+}
+
+/// Encodes text as the contents of a PDF string literal in the Windows-1252 encoding.
+fn encode_pdf_string(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => encoded.extend_from_slice(&[b'\\', c as u8]),
+            // Windows-1252 coincides with Latin-1 in these ranges
+            ' '..='~' | '\u{a0}'..='\u{ff}' => encoded.push(c as u32 as u8),
+            _ => encoded.push(b'?'),
+        }
+    }
+    encoded
+}
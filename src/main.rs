@@ -1,33 +1,118 @@
 use {
-    crate::args::{Args, Command, OutputFormat},
-    anyhow::{bail, Context},
-    chrono::{Datelike, Timelike},
+    crate::{
+        args::{Args, Command, InputFormat, OutputFormat, PhoneNumberFormat, TagsCommand},
+        config::Config,
+        labels::Label,
+    },
+    anyhow::{anyhow, bail, Context},
+    chrono::{Datelike, Timelike, Utc},
+    chrono_tz::Tz,
     clap::Parser,
     country_codes::CountryCode,
     ical::{Calendar, Event, RecurrenceFrequency, RecurrenceRule, StartDateTime},
+    ical_vcard::Contentline,
     std::{
+        collections::BTreeMap,
+        fmt::{self, Display},
+        fs,
         io::{self, BufWriter, Write},
         str::FromStr,
     },
-    store::ContactStore,
+    store::{ChangeKind, ContactStore},
+    uuid::Uuid,
+    vcard::VcardOptions,
 };
 
 mod args;
+mod config;
+mod dot;
 mod json;
+mod labels;
+mod photo;
+mod postal;
 mod store;
+mod timezone;
 mod vcard;
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    // The schema does not depend on the contact store or its configuration
+    if let Command::Schema = args.command() {
+        let writer = BufWriter::new(io::stdout());
+        return json::contacts_json_schema(writer);
+    }
+
     let store_path = args.store_path()?;
-    let store = ContactStore::from_path(store_path)?;
+    let config = Config::from_store_path(&store_path)?;
+
+    // Validation needs to work even if the contact store cannot be loaded
+    if let Command::Validate = args.command() {
+        let diagnostics = ContactStore::validate(&store_path, &config)?;
+
+        let mut writer = BufWriter::new(io::stdout());
+        for diagnostic in &diagnostics {
+            writeln!(&mut writer, "{diagnostic}")?;
+        }
+        writer.flush()?;
+
+        if !diagnostics.is_empty() {
+            bail!(
+                "Found {} problem(s) in the contact store",
+                diagnostics.len()
+            );
+        }
+        return Ok(());
+    }
+
+    let mut store = if args.lenient() || config.lenient() {
+        let (store, diagnostics) = ContactStore::from_path_lenient(&store_path, &config)?;
+        for diagnostic in diagnostics {
+            eprintln!("warning: {diagnostic}");
+        }
+        store
+    } else {
+        ContactStore::from_path(&store_path, &config)?
+    };
+
+    // Deleted contacts are reported once the deletion has been saved
+    store.on_change(|change| {
+        if change.kind == ChangeKind::Deleted {
+            println!("Deleted {}", change.contact.name);
+        }
+    });
 
     match args.command() {
+        Command::Addresses => {
+            let mut writer = BufWriter::new(io::stdout());
+
+            for contact in store.contacts()? {
+                for address in &contact.addresses {
+                    write!(&mut writer, "{}", contact.name)?;
+                    match address.ty {
+                        AddressType::Home => write!(&mut writer, " (home)")?,
+                        AddressType::Work => write!(&mut writer, " (work)")?,
+                        AddressType::Other => (),
+                    }
+                    if let Some(label) = &address.label {
+                        write!(&mut writer, " \"{label}\"")?;
+                    }
+                    writeln!(&mut writer, ":")?;
+                    for line in address.postal_lines() {
+                        writeln!(&mut writer, "    {line}")?;
+                    }
+                    writeln!(&mut writer)?;
+                }
+            }
+
+            Ok(())
+        }
         Command::Bdays => {
             let today = Date::today();
             let mut bday_items = store
-                .contacts()
+                .contacts()?
+                .iter()
+                .filter(|contact| contact.matches_tags(args.tags()))
                 .filter_map(|contact| {
                     let bday = contact.birthday.as_ref()?;
                     // Note that if bday is on the 29th February, `next_bday` may NOT represent a valid
@@ -66,12 +151,11 @@ fn main() -> anyhow::Result<()> {
             for item in bday_items {
                 writeln!(
                     &mut writer,
-                    "{year:04}-{month:02}-{day:02} {first_name} {last_name}",
+                    "{year:04}-{month:02}-{day:02} {name}",
                     year = item.next_bday.year,
                     month = item.next_bday.month,
                     day = item.next_bday.day,
-                    first_name = item.contact.name.first,
-                    last_name = item.contact.name.last,
+                    name = item.contact.name,
                 )?;
             }
 
@@ -85,7 +169,11 @@ fn main() -> anyhow::Result<()> {
                 " ",
                 env!("CARGO_PKG_VERSION")
             ));
-            for contact in store.contacts() {
+            for contact in store
+                .contacts()?
+                .iter()
+                .filter(|contact| contact.matches_tags(args.tags()))
+            {
                 let Some(bday) = &contact.birthday else {
                     continue;
                 };
@@ -109,10 +197,7 @@ fn main() -> anyhow::Result<()> {
                     // People usually don't live longer than 150 years.
                     for age in 0..150 {
                         let mut event = Event::new(StartDateTime::from(date), now_ical);
-                        event.set_summary(format!(
-                            "{} {} ({age})",
-                            contact.name.first, contact.name.last
-                        ));
+                        event.set_summary(format!("{} ({age})", contact.name));
                         calendar.add_component(event);
                         date.set_year(date.year() + 1);
                         // Not adding events after 10 years in the future saves space.
@@ -126,7 +211,7 @@ fn main() -> anyhow::Result<()> {
                     let start_date =
                         StartDateTime::from(ical::Date::new(now_ical.date.year() - 1, month, day));
                     let mut event = Event::new(start_date, now_ical);
-                    event.set_summary(format!("{} {}", contact.name.first, contact.name.last));
+                    event.set_summary(contact.name.to_string());
                     event.set_recurrence_rule(RecurrenceRule::new(RecurrenceFrequency::Yearly));
                     calendar.add_component(event);
                 }
@@ -135,24 +220,364 @@ fn main() -> anyhow::Result<()> {
             calendar.write(writer).context("Failed to write calendar")?;
             Ok(())
         }
-        Command::Export { format } => {
+        Command::Decrypt => {
+            check_modifiable(&args, &config)?;
+            ContactStore::decrypt(&store_path, &config)
+        }
+        Command::Delete { query } => {
+            check_modifiable(&args, &config)?;
+
+            // A UID or the full name take precedence over names merely containing the query
+            let contacts = store.contacts()?;
+            let exact_matches = contacts
+                .iter()
+                .filter(|contact| {
+                    contact.uid() == query
+                        || contact.name.to_string().to_lowercase() == query.to_lowercase()
+                })
+                .collect::<Vec<_>>();
+            let matches = if exact_matches.is_empty() {
+                contacts
+                    .iter()
+                    .filter(|contact| contact.name_matches(query))
+                    .collect()
+            } else {
+                exact_matches
+            };
+            let uid = match matches.as_slice() {
+                [contact] => contact.uid().to_owned(),
+                [] => bail!("No contact matches \"{query}\""),
+                _ => bail!(
+                    "\"{query}\" matches several contacts, use the UID to choose one: {}",
+                    matches
+                        .iter()
+                        .map(|contact| format!("{} ({})", contact.name, contact.uid()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+
+            let deleted = store.delete(&uid)?;
+            for contact in store.contacts()? {
+                for relation in &contact.relations {
+                    if relation.target == RelationTarget::Contact(uid.clone()) {
+                        eprintln!(
+                            "warning: {} still has a {} relation to {}",
+                            contact.name,
+                            relation.ty.name(),
+                            deleted.name,
+                        );
+                    }
+                }
+            }
+
+            store.save()
+        }
+        Command::Encrypt => {
+            check_modifiable(&args, &config)?;
+            ContactStore::encrypt(&store_path, &config)
+        }
+        Command::Export {
+            format,
+            address_types,
+            relation_types,
+            org,
+            groups,
+            photo_mode,
+            vcard_version,
+            split_dir,
+        } => {
+            if split_dir.is_some() && *format != OutputFormat::Vcard {
+                bail!("Only vCards can be exported into a directory");
+            }
             let writer = BufWriter::new(io::stdout());
 
+            let contacts = store
+                .contacts()?
+                .iter()
+                .filter(|contact| contact.matches_tags(args.tags()))
+                .filter(|contact| org.as_ref().is_none_or(|org| contact.org_matches(org)))
+                .cloned()
+                .map(|mut contact| {
+                    if !address_types.is_empty() {
+                        contact
+                            .addresses
+                            .retain(|address| address_types.contains(&address.ty));
+                    }
+                    if !relation_types.is_empty() {
+                        contact
+                            .relations
+                            .retain(|relation| relation_types.contains(&relation.ty));
+                    }
+                    contact
+                })
+                .collect::<Vec<_>>();
+
             match format {
-                OutputFormat::Json => json::contacts_to_json(writer, store.contacts()),
-                OutputFormat::Vcard => vcard::contacts_to_vcard(writer, store.contacts()),
+                OutputFormat::Dot => dot::contacts_to_dot(writer, &contacts),
+                OutputFormat::Json => json::contacts_to_json(writer, contacts.iter()),
+                OutputFormat::Vcard => {
+                    let options = VcardOptions {
+                        groups: *groups,
+                        photo_mode: *photo_mode,
+                        photos_path: ContactStore::photos_path(&store_path),
+                        derive_time_zone: config.derive_time_zone(),
+                        version: *vcard_version,
+                    };
+                    match split_dir {
+                        Some(split_dir) => {
+                            // Files of the contacts that were filtered out must be kept
+                            let remove_stale = args.tags().is_empty() && org.is_none();
+                            let summary = vcard::contacts_to_vcard_dir(
+                                split_dir,
+                                &contacts,
+                                &options,
+                                remove_stale,
+                            )?;
+                            println!(
+                                "Wrote {} file(s), {} unchanged, removed {} file(s)",
+                                summary.written, summary.unchanged, summary.removed
+                            );
+                            Ok(())
+                        }
+                        None => vcard::contacts_to_vcard(writer, &contacts, &options),
+                    }
+                }
             }
         }
-        Command::Names => {
+        Command::Import { path, format } => {
+            check_modifiable(&args, &config)?;
+
+            let input = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let (contacts, warnings) = match format {
+                InputFormat::Json => {
+                    let (contacts, diagnostics) =
+                        json::contacts_from_json_lenient(&input, config.default_region());
+                    if let Some(diagnostic) = diagnostics.into_iter().next() {
+                        return Err(anyhow!("{}:{diagnostic}", path.display()))
+                            .with_context(|| format!("Failed to import {}", path.display()));
+                    }
+                    (contacts, Vec::new())
+                }
+                InputFormat::Vcard => {
+                    vcard::contacts_from_vcard(&input, config.default_region())
+                        .with_context(|| format!("Failed to import {}", path.display()))?
+                }
+            };
+            for warning in warnings {
+                eprintln!("warning: {}: {warning}", path.display());
+            }
+
+            let count = contacts.len();
+            for mut contact in contacts {
+                if contact.uid.is_empty() {
+                    contact.uid = Contact::new_uid();
+                }
+                let name = contact.name.to_string();
+                let uid = contact.uid().to_owned();
+                let result = match store.get(&uid)? {
+                    Some(_) => store.update(&uid, contact),
+                    None => store.insert(contact),
+                };
+                result.with_context(|| format!("Failed to import {name}"))?;
+            }
+            store.save()?;
+            println!("Imported {count} contact(s)");
+            Ok(())
+        }
+        Command::Labels {
+            query,
+            format,
+            sheet,
+            address_types,
+            origin,
+        } => {
+            let origin = origin.or(config.default_region());
+
+            let mut labels = Vec::new();
+            for contact in store.contacts()? {
+                if !contact.matches_tags(args.tags()) {
+                    continue;
+                }
+                if let Some(query) = query {
+                    if !contact.name_matches(query) {
+                        continue;
+                    }
+                }
+
+                let recipient = contact.name.to_string();
+                let Some(address) = contact.label_address(address_types) else {
+                    eprintln!("No address: {recipient}");
+                    continue;
+                };
+
+                let mut lines = vec![recipient.clone()];
+                lines.extend(address.envelope_lines(origin));
+                labels.push(Label {
+                    recipient,
+                    address,
+                    lines,
+                });
+            }
+
+            let writer = BufWriter::new(io::stdout());
+            labels::write_labels(writer, &labels, *format, *sheet)
+        }
+        Command::Names { org } => {
+            let mut writer = BufWriter::new(io::stdout());
+
+            let mut contacts = store
+                .contacts()?
+                .iter()
+                .filter(|contact| contact.matches_tags(args.tags()))
+                .filter(|contact| org.as_ref().is_none_or(|org| contact.org_matches(org)))
+                .collect::<Vec<_>>();
+            contacts.sort_by_cached_key(|contact| contact.name.sort_key());
+            for contact in contacts {
+                let name = &contact.name;
+                write!(&mut writer, "{name}")?;
+                if let Some(nickname) = &name.nickname {
+                    // Single-word names consisting of only a nickname are displayed as such
+                    if !name.first.is_empty() || !name.last.is_empty() {
+                        write!(&mut writer, " \"{nickname}\"")?;
+                    }
+                }
+                writeln!(&mut writer)?;
+            }
+
+            Ok(())
+        }
+        Command::Now { query } => {
+            let now = Utc::now();
             let mut writer = BufWriter::new(io::stdout());
 
-            for contact in store.contacts() {
-                writeln!(&mut writer, "{} {}", contact.name.first, contact.name.last)?;
+            for contact in store.contacts()? {
+                if let Some(query) = query {
+                    if !contact.name_matches(query) {
+                        continue;
+                    }
+                }
+
+                match contact.local_time_zone(config.derive_time_zone()) {
+                    Some(time_zone) => {
+                        let local_time = now.with_timezone(&time_zone);
+                        writeln!(
+                            &mut writer,
+                            "{}: {} ({time_zone})",
+                            contact.name,
+                            local_time.format("%a %Y-%m-%d %H:%M %Z"),
+                        )?;
+                    }
+                    None => writeln!(&mut writer, "{}: unknown time zone", contact.name)?,
+                }
             }
 
             Ok(())
         }
+        Command::Phones { format } => {
+            let mut writer = BufWriter::new(io::stdout());
+
+            for contact in store.contacts()? {
+                for phone_number in &contact.phone_numbers {
+                    write!(
+                        &mut writer,
+                        "{}: {}",
+                        contact.name,
+                        phone_number.format(*format),
+                    )?;
+                    let types = phone_number
+                        .types
+                        .iter()
+                        .map(|ty| ty.name())
+                        .collect::<Vec<_>>();
+                    if !types.is_empty() {
+                        write!(&mut writer, " ({})", types.join(", "))?;
+                    }
+                    if let Some(label) = &phone_number.label {
+                        write!(&mut writer, " \"{label}\"")?;
+                    }
+                    writeln!(&mut writer)?;
+                }
+            }
+
+            Ok(())
+        }
+        Command::Search { query } => {
+            let mut writer = BufWriter::new(io::stdout());
+
+            for contact in store.search(query)? {
+                if contact.matches_tags(args.tags()) {
+                    writeln!(&mut writer, "{}", contact.name)?;
+                }
+            }
+
+            Ok(())
+        }
+        Command::Tags { command: None } => {
+            let mut counts = BTreeMap::<&str, usize>::new();
+            for contact in store.contacts()? {
+                for tag in &contact.tags {
+                    *counts.entry(tag).or_default() += 1;
+                }
+            }
+
+            let mut writer = BufWriter::new(io::stdout());
+            for (tag, count) in counts {
+                writeln!(&mut writer, "{tag} ({count})")?;
+            }
+
+            Ok(())
+        }
+        Command::Tags {
+            command: Some(command),
+        } => {
+            let (tags, into) = match command {
+                TagsCommand::Merge { tags, into } => (tags.as_slice(), into),
+                TagsCommand::Rename { from, to } => (std::slice::from_ref(from), to),
+            };
+            validate_tag(into)?;
+            check_modifiable(&args, &config)?;
+
+            let uids = store
+                .contacts()?
+                .iter()
+                .filter(|contact| contact.tags.iter().any(|tag| tags.contains(tag)))
+                .map(|contact| contact.uid().to_owned())
+                .collect::<Vec<_>>();
+            if uids.is_empty() {
+                bail!("No contact is tagged with {}", tags.join(", "));
+            }
+
+            for uid in &uids {
+                let mut contact = store.get(uid)?.expect("contact exists");
+                let mut new_tags = Vec::with_capacity(contact.tags.len());
+                for tag in &contact.tags {
+                    let tag = if tags.contains(tag) { into } else { tag };
+                    if !new_tags.contains(tag) {
+                        new_tags.push(tag.to_owned());
+                    }
+                }
+                contact.tags = new_tags;
+                store.update(uid, contact)?;
+            }
+            store.save()?;
+            println!("Updated {} contact(s)", uids.len());
+            Ok(())
+        }
+        Command::Schema | Command::Validate => unreachable!("handled before loading the store"),
+    }
+}
+
+/// Checks if the contact store may be modified.
+///
+/// In lenient mode, invalid contacts are skipped when loading the store, so saving it would lose
+/// them.
+fn check_modifiable(args: &Args, config: &Config) -> anyhow::Result<()> {
+    if args.lenient() || config.lenient() {
+        bail!("The contact store cannot be modified in lenient mode, as invalid contacts would be lost");
     }
+    Ok(())
 }
 
 #[derive(Clone, Debug)]
@@ -161,19 +586,200 @@ struct BdayItem {
     contact: Contact,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Contact {
+    /// The unique identifier of the contact as a URI, see [`Contact::new_uid`]
+    uid: String,
     name: Name,
     birthday: Option<PartialDate>,
     phone_numbers: Vec<PhoneNumber>,
-    email_addresses: Vec<String>,
-    address: Option<Address>,
+    email_addresses: Vec<EmailAddress>,
+    addresses: Vec<Address>,
+    organization: Option<Organization>,
+    /// The job title, e.g. "Research Scientist"
+    title: Option<String>,
+    /// The function within the organization, e.g. "Project Leader"
+    role: Option<String>,
+    notes: Option<String>,
+    relations: Vec<Relation>,
+    /// The file name of the photo in the photos directory of the store
+    photo: Option<String>,
+    geo: Option<Geo>,
+    time_zone: Option<Tz>,
+    /// Language tags (BCP 47) of the languages the contact speaks, the most preferred first
+    languages: Vec<String>,
+    gender: Option<Gender>,
+    websites: Vec<Website>,
+    /// Instant messaging handles, exported as vCard `IMPP`
+    impp: Vec<ImppHandle>,
+    social_profiles: Vec<SocialProfile>,
+    /// Tags used to group contacts, e.g. "family". See [`validate_tag`] for valid tags.
+    tags: Vec<String>,
+    /// Custom fields. The keys are valid vCard property names (see [`validate_extra_key`]).
+    extra: BTreeMap<String, String>,
+    /// Properties of imported vCards that are not understood, exported again verbatim
+    vcard_properties: Vec<Contentline<'static>>,
+}
+
+impl Contact {
+    /// The unique identifier of this contact
+    fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    /// A new random identifier for a contact, e.g. "urn:uuid:4b2b4f4e-..."
+    ///
+    /// Contacts are given one when they are created or imported without an identifier. It is
+    /// stored along with the contact, so it stays the same when the contact is renamed.
+    fn new_uid() -> String {
+        format!("urn:uuid:{}", Uuid::new_v4())
+    }
+
+    /// An identifier derived from `seed`, for contacts that are stored without one
+    ///
+    /// Unlike [`Contact::new_uid`], reading the same contact again gives it the same identifier,
+    /// so that it does not change until the contact is saved with it.
+    fn derived_uid(seed: &str) -> String {
+        let uid = Uuid::new_v5(
+            &Uuid::NAMESPACE_URL,
+            format!("tactful:contact:{seed}").as_bytes(),
+        );
+        format!("urn:uuid:{uid}")
+    }
+
+    /// The time zone of this contact
+    ///
+    /// If no time zone is stored and `derive_from_country` is set, the time zone of the country of
+    /// the most preferred address is used, as long as the country has a single time zone.
+    fn local_time_zone(&self, derive_from_country: bool) -> Option<Tz> {
+        self.time_zone.or_else(|| {
+            if !derive_from_country {
+                return None;
+            }
+            let address = self
+                .addresses
+                .iter()
+                .min_by_key(|address| address.pref.unwrap_or(u8::MAX))?;
+            timezone::country_time_zone(address.country)
+        })
+    }
+
+    /// Checks if this contact has any of the given tags. Every contact matches an empty list.
+    fn matches_tags(&self, tags: &[String]) -> bool {
+        tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag))
+    }
+
+    /// Returns the most preferred address of one of the given types, or of any type if the list is
+    /// empty
+    fn label_address(&self, address_types: &[AddressType]) -> Option<&Address> {
+        self.addresses
+            .iter()
+            .filter(|address| address_types.is_empty() || address_types.contains(&address.ty))
+            .min_by_key(|address| address.pref.unwrap_or(u8::MAX))
+    }
+
+    /// Checks if the full name or the nickname of this contact contains the query
+    /// (case-insensitive)
+    fn name_matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.name.to_string().to_lowercase().contains(&query)
+            || self
+                .name
+                .nickname
+                .as_ref()
+                .is_some_and(|nickname| nickname.to_lowercase().contains(&query))
+    }
+
+    /// Checks if the organization or the department of this contact contains the query
+    /// (case-insensitive)
+    fn org_matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.organization.as_ref().is_some_and(|organization| {
+            organization.name.to_lowercase().contains(&query)
+                || organization
+                    .department
+                    .as_ref()
+                    .is_some_and(|department| department.to_lowercase().contains(&query))
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
+struct Organization {
+    name: String,
+    department: Option<String>,
+}
+
+/// The name of a contact, following the structure of the vCard `N` property
+///
+/// Contacts with a single-word name may leave either `first` or `last` empty.
+#[derive(Clone, Debug, Default)]
 struct Name {
+    /// Honorific prefixes, e.g. "Dr." or "Prof."
+    prefixes: Vec<String>,
     first: String,
+    middle: Vec<String>,
     last: String,
+    /// Honorific suffixes, e.g. "Jr." or "PhD"
+    suffixes: Vec<String>,
+    nickname: Option<String>,
+    /// Overrides how the name is sorted, e.g. the phonetic reading of a name written in Kanji
+    sort_as: Option<String>,
+}
+
+impl Name {
+    /// Checks if this name is valid, i.e. if there is anything to call the contact by.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.first.is_empty() && self.last.is_empty() && self.nickname.is_none() {
+            bail!("Name must contain a first name, a last name or a nickname");
+        }
+        Ok(())
+    }
+
+    /// The key by which contacts are sorted: the sort name if there is one, the last name followed
+    /// by the first and middle names otherwise. Falls back to the nickname like [`Display`].
+    fn sort_key(&self) -> String {
+        if let Some(sort_as) = &self.sort_as {
+            return sort_as.to_lowercase();
+        }
+        if self.first.is_empty() && self.last.is_empty() {
+            if let Some(nickname) = &self.nickname {
+                return nickname.to_lowercase();
+            }
+        }
+        [&self.last, &self.first]
+            .into_iter()
+            .chain(&self.middle)
+            .filter(|part| !part.is_empty())
+            .map(|part| part.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+}
+
+/// Formats the full name, e.g. "Dr. John Michael Smith Jr.". The nickname is only used if the name
+/// is otherwise empty.
+impl Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = self
+            .prefixes
+            .iter()
+            .chain([&self.first])
+            .chain(&self.middle)
+            .chain([&self.last])
+            .chain(&self.suffixes)
+            .filter(|part| !part.is_empty())
+            .map(|part| part.as_str())
+            .collect::<Vec<_>>();
+
+        if self.first.is_empty() && self.last.is_empty() {
+            if let Some(nickname) = &self.nickname {
+                return write!(f, "{nickname}");
+            }
+        }
+        write!(f, "{}", parts.join(" "))
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -298,6 +904,65 @@ impl PartialDate {
         Ok(date)
     }
 
+    /// Parses a date in one of the formats produced by [`PartialDate::to_vcard_string_repr`].
+    /// Dates in the extended format of ISO 8601 (`YYYY-MM-DD`) are accepted as well.
+    fn from_vcard_string_repr(string_repr: &str) -> anyhow::Result<Self> {
+        let error_message = || format!("Invalid date format: \"{string_repr}\"");
+
+        let digits = |range: std::ops::Range<usize>| -> anyhow::Result<Option<u16>> {
+            let component = string_repr.get(range).with_context(error_message)?;
+            if !component.chars().all(|c| c.is_ascii_digit()) {
+                bail!(error_message());
+            }
+            Ok(Some(u16::from_str(component).with_context(error_message)?))
+        };
+
+        let bytes = string_repr.as_bytes();
+        let date = match (bytes.len(), bytes) {
+            (5, [b'-', b'-', b'-', ..]) => Self {
+                year: None,
+                month: None,
+                day: digits(3..5)?,
+            },
+            (4, [b'-', b'-', ..]) => Self {
+                year: None,
+                month: digits(2..4)?,
+                day: None,
+            },
+            (6, [b'-', b'-', ..]) => Self {
+                year: None,
+                month: digits(2..4)?,
+                day: digits(4..6)?,
+            },
+            (4, _) => Self {
+                year: digits(0..4)?,
+                month: None,
+                day: None,
+            },
+            (7, [_, _, _, _, b'-', ..]) => Self {
+                year: digits(0..4)?,
+                month: digits(5..7)?,
+                day: None,
+            },
+            (8, _) => Self {
+                year: digits(0..4)?,
+                month: digits(4..6)?,
+                day: digits(6..8)?,
+            },
+            (10, [_, _, _, _, b'-', _, _, b'-', ..]) => Self {
+                year: digits(0..4)?,
+                month: digits(5..7)?,
+                day: digits(8..10)?,
+            },
+            _ => bail!(error_message()),
+        };
+
+        date.validate()
+            .with_context(|| format!("Invalid date \"{string_repr}\""))?;
+
+        Ok(date)
+    }
+
     fn to_vcard_string_repr(&self) -> anyhow::Result<String> {
         if let Some(year) = self.year {
             if year > 9999 {
@@ -336,52 +1001,800 @@ impl From<Date> for PartialDate {
 /// [`PhoneNumber::validate`] to validate phone numbers.
 #[derive(Clone, Debug)]
 struct PhoneNumber {
+    /// The phone number as it was entered
     number: String,
-    ty: PhoneNumberType,
+    parsed: phonenumber::PhoneNumber,
+    types: Vec<PhoneNumberType>,
+    /// A free-form label, e.g. "Grandma's landline"
+    label: Option<String>,
+    /// The preference of this phone number, from 1 (most preferred) to 100 (least preferred)
+    pref: Option<u8>,
 }
 
 impl PhoneNumber {
+    /// Parses a phone number.
+    ///
+    /// Numbers in national format are resolved against the given region.
+    fn parse(
+        number: String,
+        types: Vec<PhoneNumberType>,
+        label: Option<String>,
+        pref: Option<u8>,
+        region: Option<CountryCode>,
+    ) -> anyhow::Result<Self> {
+        let region_id =
+            region.and_then(|region| phonenumber::country::Id::from_str(region.alpha2).ok());
+        let parsed = match phonenumber::parse(region_id, &number) {
+            Ok(parsed) => parsed,
+            Err(error) if region_id.is_none() => {
+                return Err(error).with_context(|| {
+                    format!(
+                        "Invalid phone number \"{number}\" (national numbers require the contact \
+                        to have an address or a default region to be configured)"
+                    )
+                })
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("Invalid phone number \"{number}\""))
+            }
+        };
+
+        let phone_number = Self {
+            number,
+            parsed,
+            types,
+            label,
+            pref,
+        };
+        phone_number.validate()?;
+        Ok(phone_number)
+    }
+
     /// Checks if this phone number is valid.
     ///
-    /// This is very rudimentary and does not check a great many things.
+    /// A phone number is valid if it is possible in the region it belongs to.
     fn validate(&self) -> anyhow::Result<()> {
-        let chars = self
-            .number
-            .chars()
-            .filter(|c| !c.is_whitespace())
-            .collect::<Vec<_>>();
+        if !self.parsed.is_valid() {
+            bail!(
+                "Phone number \"{}\" is not possible in its region",
+                self.number
+            );
+        }
+
+        validate_pref(self.pref)
+    }
+
+    fn format(&self, format: PhoneNumberFormat) -> String {
+        let mode = match format {
+            PhoneNumberFormat::E164 => phonenumber::Mode::E164,
+            PhoneNumberFormat::International => phonenumber::Mode::International,
+            PhoneNumberFormat::National => phonenumber::Mode::National,
+        };
+        self.parsed.format().mode(mode).to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PhoneNumberType {
+    Mobile,
+    Home,
+    Work,
+    Fax,
+    Pager,
+    /// The main number of an organization
+    Main,
+    Voip,
+    /// Supports text messages (SMS)
+    Text,
+    Voice,
+    Video,
+    /// A telecommunication device for people with hearing or speech difficulties
+    Textphone,
+}
+
+impl PhoneNumberType {
+    fn name(self) -> &'static str {
+        match self {
+            PhoneNumberType::Mobile => "mobile",
+            PhoneNumberType::Home => "home",
+            PhoneNumberType::Work => "work",
+            PhoneNumberType::Fax => "fax",
+            PhoneNumberType::Pager => "pager",
+            PhoneNumberType::Main => "main",
+            PhoneNumberType::Voip => "voip",
+            PhoneNumberType::Text => "text",
+            PhoneNumberType::Voice => "voice",
+            PhoneNumberType::Video => "video",
+            PhoneNumberType::Textphone => "textphone",
+        }
+    }
+}
+
+/// The gender of a contact, following the vCard `GENDER` property
+#[derive(Clone, Debug)]
+struct Gender {
+    sex: Option<Sex>,
+    /// Free-form gender identity
+    identity: Option<String>,
+}
 
-        match chars.first() {
-            Some(&first_char) => {
-                if !(first_char.is_ascii_digit() || first_char == '+') {
-                    bail!("Phone number must begin with a digit or a '+'");
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Sex {
+    Male,
+    Female,
+    Other,
+    NotApplicable,
+    Unknown,
+}
+
+/// Checks if `tag` is a well-formed language tag (see RFC 5646), e.g. "de-CH".
+///
+/// This only checks the general structure of the tag, not whether its subtags are registered.
+fn validate_language_tag(tag: &str) -> anyhow::Result<()> {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    let valid_language = ((2..=8).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic()))
+        || matches!(language, "x" | "i");
+    if !valid_language {
+        bail!("Invalid language tag \"{tag}\"");
+    }
+    if !subtags.all(|subtag| {
+        (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+    }) {
+        bail!("Invalid language tag \"{tag}\"");
+    }
+    Ok(())
+}
+
+/// Geographic coordinates in degrees (WGS 84)
+#[derive(Clone, Copy, Debug)]
+struct Geo {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl Geo {
+    fn validate(&self) -> anyhow::Result<()> {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            bail!("Latitude must be between -90 and 90 degrees");
+        }
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            bail!("Longitude must be between -180 and 180 degrees");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Website {
+    url: String,
+    ty: WebsiteType,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WebsiteType {
+    Home,
+    Work,
+    Blog,
+    Other,
+}
+
+/// Represents a handle of an instant messaging service.
+///
+/// All functions and structs that take [`ImppHandle`]s assume that the handle is valid. Use
+/// [`ImppHandle::validate`] to validate handles.
+#[derive(Clone, Debug)]
+struct ImppHandle {
+    service: ImppService,
+    /// The handle as usually written for the service, e.g. "@alice:example.org" for Matrix
+    handle: String,
+}
+
+impl ImppHandle {
+    fn validate(&self) -> anyhow::Result<()> {
+        match self.service {
+            ImppService::Matrix => {
+                let valid = self
+                    .handle
+                    .strip_prefix('@')
+                    .and_then(|handle| handle.split_once(':'))
+                    .is_some_and(|(user, server)| !user.is_empty() && !server.is_empty());
+                if !valid {
+                    bail!("Matrix IDs must have the form \"@user:server\"");
                 }
             }
-            None => {
-                bail!("Phone number cannot be empty");
+            ImppService::Signal => {
+                let valid = self.handle.strip_prefix('+').is_some_and(|digits| {
+                    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+                });
+                if !valid {
+                    bail!("Signal handles must be phone numbers in E.164 format, e.g. \"+41791234567\"");
+                }
+            }
+            ImppService::Xmpp => {
+                if !self.handle.contains('@') {
+                    bail!("XMPP addresses must have the form \"user@server\"");
+                }
             }
+            ImppService::Telegram => {
+                let username = self.handle.trim_start_matches('@');
+                if username.is_empty()
+                    || !username
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    bail!("Invalid Telegram username: \"{}\"", self.handle);
+                }
+            }
+            ImppService::Other => validate_uri(&self.handle)?,
+        }
+        if self.handle.chars().any(char::is_whitespace) {
+            bail!("Handle cannot contain whitespace");
         }
+        Ok(())
+    }
 
-        if !chars.iter().skip(1).all(char::is_ascii_digit) {
-            bail!("All except the first character of a phone number must be digits");
+    /// The handle as a URI, as used for vCard `IMPP`
+    fn uri(&self) -> String {
+        match self.service {
+            ImppService::Matrix => format!("matrix:u/{}", &self.handle[1..]),
+            ImppService::Signal => format!("sgnl://signal.me/#p/{}", self.handle),
+            ImppService::Xmpp => format!("xmpp:{}", self.handle),
+            ImppService::Telegram => {
+                format!("https://t.me/{}", self.handle.trim_start_matches('@'))
+            }
+            ImppService::Other => self.handle.to_owned(),
         }
+    }
 
+    /// Reverses [`ImppHandle::uri`]. URIs of unknown services are kept as handles of
+    /// [`ImppService::Other`].
+    fn from_uri(uri: &str) -> Self {
+        let (service, handle) = if let Some(user) = uri.strip_prefix("matrix:u/") {
+            (ImppService::Matrix, format!("@{user}"))
+        } else if let Some(number) = uri.strip_prefix("sgnl://signal.me/#p/") {
+            (ImppService::Signal, number.to_owned())
+        } else if let Some(address) = uri.strip_prefix("xmpp:") {
+            (ImppService::Xmpp, address.to_owned())
+        } else if let Some(username) = uri.strip_prefix("https://t.me/") {
+            (ImppService::Telegram, format!("@{username}"))
+        } else {
+            (ImppService::Other, uri.to_owned())
+        };
+        ImppHandle { service, handle }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ImppService {
+    Matrix,
+    Signal,
+    Xmpp,
+    Telegram,
+    /// Any other service, the handle is a URI
+    Other,
+}
+
+impl ImppService {
+    fn name(self) -> &'static str {
+        match self {
+            ImppService::Matrix => "Matrix",
+            ImppService::Signal => "Signal",
+            ImppService::Xmpp => "XMPP",
+            ImppService::Telegram => "Telegram",
+            ImppService::Other => "Other",
+        }
+    }
+}
+
+/// Represents a profile on a social network.
+///
+/// All functions and structs that take [`SocialProfile`]s assume that the profile is valid. Use
+/// [`SocialProfile::validate`] to validate profiles.
+#[derive(Clone, Debug)]
+struct SocialProfile {
+    service: SocialService,
+    /// The user name as usually written for the service, e.g. "@alice@mastodon.social" for
+    /// Mastodon
+    handle: String,
+}
+
+impl SocialProfile {
+    fn validate(&self) -> anyhow::Result<()> {
+        match self.service {
+            SocialService::Mastodon => {
+                let valid = self
+                    .handle
+                    .strip_prefix('@')
+                    .and_then(|handle| handle.split_once('@'))
+                    .is_some_and(|(user, server)| !user.is_empty() && server.contains('.'));
+                if !valid {
+                    bail!("Mastodon handles must have the form \"@user@server\"");
+                }
+            }
+            SocialService::Github | SocialService::Linkedin | SocialService::Twitter => {
+                let username = self.user();
+                if username.is_empty()
+                    || !username
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+                {
+                    bail!(
+                        "Invalid {} user name: \"{}\"",
+                        self.service.name(),
+                        self.handle
+                    );
+                }
+            }
+            SocialService::Other => validate_uri(&self.handle)?,
+        }
         Ok(())
     }
+
+    /// The user name without decorations like a leading '@'
+    fn user(&self) -> &str {
+        match self.service {
+            SocialService::Mastodon => self.handle[1..]
+                .split_once('@')
+                .map_or(&self.handle, |(user, _)| user),
+            SocialService::Github | SocialService::Linkedin | SocialService::Twitter => {
+                self.handle.trim_start_matches('@')
+            }
+            SocialService::Other => &self.handle,
+        }
+    }
+
+    /// The URL of the profile
+    fn url(&self) -> String {
+        match self.service {
+            SocialService::Mastodon => {
+                let (user, server) = self.handle[1..]
+                    .split_once('@')
+                    .expect("valid Mastodon handle");
+                format!("https://{server}/@{user}")
+            }
+            SocialService::Github => format!("https://github.com/{}", self.user()),
+            SocialService::Linkedin => format!("https://www.linkedin.com/in/{}", self.user()),
+            SocialService::Twitter => format!("https://x.com/{}", self.user()),
+            SocialService::Other => self.handle.to_owned(),
+        }
+    }
+
+    /// Reverses [`SocialProfile::url`]. Returns `None` if the URL is not a profile URL of the
+    /// service.
+    fn from_url(service: SocialService, url: &str) -> Option<Self> {
+        let handle = match service {
+            SocialService::Mastodon => {
+                let (server, user) = url.strip_prefix("https://")?.split_once("/@")?;
+                format!("@{user}@{server}")
+            }
+            SocialService::Github => url.strip_prefix("https://github.com/")?.to_owned(),
+            SocialService::Linkedin => url.strip_prefix("https://www.linkedin.com/in/")?.to_owned(),
+            SocialService::Twitter => url.strip_prefix("https://x.com/")?.to_owned(),
+            SocialService::Other => url.to_owned(),
+        };
+        Some(SocialProfile { service, handle })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum PhoneNumberType {
-    Mobile,
+enum SocialService {
+    Mastodon,
+    Github,
+    Linkedin,
+    Twitter,
+    /// Any other service, the handle is the URL of the profile
+    Other,
+}
+
+impl SocialService {
+    fn name(self) -> &'static str {
+        match self {
+            SocialService::Mastodon => "Mastodon",
+            SocialService::Github => "GitHub",
+            SocialService::Linkedin => "LinkedIn",
+            SocialService::Twitter => "Twitter",
+            SocialService::Other => "Other",
+        }
+    }
+}
+
+/// A relation of a contact to another person
+#[derive(Clone, Debug)]
+struct Relation {
+    ty: RelationType,
+    target: RelationTarget,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum RelationTarget {
+    /// Another contact in the store, referenced by its UID
+    Contact(String),
+    /// A person that is not in the store, described by free text
+    Person(String),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RelationType {
+    Spouse,
+    Child,
+    Parent,
+    Sibling,
+    Kin,
+    Friend,
+    Colleague,
+    Neighbor,
+    Manager,
+    Assistant,
+    Emergency,
+    Other,
+}
+
+impl RelationType {
+    fn name(self) -> &'static str {
+        match self {
+            RelationType::Spouse => "spouse",
+            RelationType::Child => "child",
+            RelationType::Parent => "parent",
+            RelationType::Sibling => "sibling",
+            RelationType::Kin => "kin",
+            RelationType::Friend => "friend",
+            RelationType::Colleague => "colleague",
+            RelationType::Neighbor => "neighbor",
+            RelationType::Manager => "manager",
+            RelationType::Assistant => "assistant",
+            RelationType::Emergency => "emergency",
+            RelationType::Other => "other",
+        }
+    }
+}
+
+impl FromStr for RelationType {
+    type Err = anyhow::Error;
+    fn from_str(relation_type: &str) -> anyhow::Result<Self> {
+        Ok(match relation_type.to_ascii_lowercase().as_str() {
+            "spouse" => RelationType::Spouse,
+            "child" => RelationType::Child,
+            "parent" => RelationType::Parent,
+            "sibling" => RelationType::Sibling,
+            "kin" => RelationType::Kin,
+            "friend" => RelationType::Friend,
+            "colleague" => RelationType::Colleague,
+            "neighbor" => RelationType::Neighbor,
+            "manager" => RelationType::Manager,
+            "assistant" => RelationType::Assistant,
+            "emergency" => RelationType::Emergency,
+            "other" => RelationType::Other,
+            _ => bail!("Invalid relation type"),
+        })
+    }
+}
+
+/// Represents an email address.
+///
+/// All functions and structs that take [`EmailAddress`]es assume that the email address is valid.
+/// All functions that produce [`EmailAddress`]es only produce valid email addresses. Use
+/// [`EmailAddress::validate`] to validate email addresses.
+#[derive(Clone, Debug)]
+struct EmailAddress {
+    address: String,
+    ty: EmailAddressType,
+    /// Whether this is the preferred email address of the contact
+    pref: bool,
+}
+
+impl EmailAddress {
+    /// Checks if this email address is valid.
+    ///
+    /// This is rudimentary and only checks the general structure of the address. In particular, it
+    /// rejects some addresses that are technically valid, e.g. those with quoted local parts.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self
+            .address
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control())
+        {
+            bail!("Email address cannot contain whitespace");
+        }
+
+        let Some((local_part, domain)) = self.address.split_once('@') else {
+            bail!("Email address must contain an '@'");
+        };
+
+        if local_part.is_empty() {
+            bail!("Email address must contain a local part before the '@'");
+        }
+        if local_part.len() > 64 {
+            bail!("The local part of an email address cannot be longer than 64 characters");
+        }
+        if domain.contains('@') {
+            bail!("Email address must contain exactly one '@'");
+        }
+
+        if !domain.contains('.') || domain.split('.').any(str::is_empty) {
+            bail!("Invalid domain: \"{domain}\"");
+        }
+        if !domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '.')
+        {
+            bail!("Domain can only contain letters, digits, '-' and '.'");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EmailAddressType {
     Home,
     Work,
+    Other,
 }
 
+/// A postal address.
+///
+/// The components correspond to those of the vCard `ADR` property. Use [`Address::postal_lines`]
+/// to format an address according to the conventions of its country.
 #[derive(Clone, Debug)]
 struct Address {
+    /// The street name, may be empty (e.g. for addresses consisting only of a PO box)
     street: String,
+    /// The house number, may be empty
     number: String,
+    /// Apartment, suite, floor, etc.
+    extended: Option<String>,
+    po_box: Option<String>,
     locality: String,
+    /// State, province, county, etc.
+    region: Option<String>,
     postal_code: String,
     country: CountryCode,
+    ty: AddressType,
+    /// A free-form label, e.g. "Holiday home"
+    label: Option<String>,
+    /// The preference of this address, from 1 (most preferred) to 100 (least preferred)
+    pref: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AddressType {
+    Home,
+    Work,
+    Other,
+}
+
+impl FromStr for AddressType {
+    type Err = anyhow::Error;
+    fn from_str(address_type: &str) -> anyhow::Result<Self> {
+        Ok(match address_type.to_ascii_lowercase().as_str() {
+            "home" => AddressType::Home,
+            "work" => AddressType::Work,
+            "other" => AddressType::Other,
+            _ => bail!("Invalid address type"),
+        })
+    }
+}
+
+/// Checks if a preference (as used for phone numbers and addresses) is valid.
+///
+/// Just like in vCard, preferences range from 1 (most preferred) to 100 (least preferred).
+fn validate_pref(pref: Option<u8>) -> anyhow::Result<()> {
+    if let Some(pref) = pref {
+        if !(1..=100).contains(&pref) {
+            bail!("Invalid preference: {pref} (must be between 1 and 100)");
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks if `uri` is a URI, as vCard expects for properties like `UID`, `MEMBER` or `URL`.
+///
+/// This only checks for a scheme followed by a colon, e.g. "urn:uuid:..." or "https://...".
+fn validate_uri(uri: &str) -> anyhow::Result<()> {
+    let Some((scheme, _)) = uri.split_once(':') else {
+        bail!("\"{uri}\" is not a URI, it must start with a scheme like \"https:\" or \"urn:\"");
+    };
+    if !scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    {
+        bail!("Invalid URI scheme: \"{scheme}\"");
+    }
+    if uri.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        bail!("URI cannot contain whitespace");
+    }
+    Ok(())
+}
+
+/// Checks if `tag` can be used as a tag.
+///
+/// Tags are exported as a comma-separated vCard `CATEGORIES` list, so they cannot contain commas.
+fn validate_tag(tag: &str) -> anyhow::Result<()> {
+    if tag.trim().is_empty() {
+        bail!("Tag cannot be empty");
+    }
+    if tag.contains(',') {
+        bail!("Tag \"{tag}\" cannot contain ','");
+    }
+    if tag.chars().any(char::is_control) {
+        bail!("Tag \"{tag}\" cannot contain control characters");
+    }
+    Ok(())
+}
+
+/// Checks if `key` can be used as the name of a custom field.
+///
+/// Custom fields are exported as vCard `X-` properties, so their names are restricted to letters,
+/// digits and hyphens. Names of `X-` properties that have a meaning of their own (e.g. the labels
+/// of grouped properties, which are kept verbatim) are reserved.
+fn validate_extra_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() {
+        bail!("The name of a custom field cannot be empty");
+    }
+    if !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        bail!("Invalid custom field name \"{key}\": only letters, digits and '-' are allowed");
+    }
+    if [
+        "ablabel",
+        "socialprofile",
+        "addressbookserver-kind",
+        "addressbookserver-member",
+    ]
+    .iter()
+    .any(|reserved| key.eq_ignore_ascii_case(reserved))
+    {
+        bail!(
+            "Invalid custom field name \"{key}\": X-{} is reserved",
+            key.to_ascii_uppercase()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phone_number(number: &str, region: Option<CountryCode>) -> anyhow::Result<PhoneNumber> {
+        PhoneNumber::parse(number.to_owned(), Vec::new(), None, None, region)
+    }
+
+    #[test]
+    fn phone_numbers_are_resolved_against_the_region() {
+        let number = phone_number("+41 79 123 45 67", None).unwrap();
+        assert_eq!(number.format(PhoneNumberFormat::E164), "+41791234567");
+
+        // National numbers need a region
+        assert!(phone_number("079 123 45 67", None).is_err());
+        let number = phone_number("079 123 45 67", Some(country_codes::CH)).unwrap();
+        assert_eq!(number.format(PhoneNumberFormat::E164), "+41791234567");
+        assert_eq!(
+            number.format(PhoneNumberFormat::International),
+            "+41 79 123 45 67"
+        );
+        let number = phone_number("(202) 555-0123", Some(country_codes::US)).unwrap();
+        assert_eq!(number.format(PhoneNumberFormat::E164), "+12025550123");
+
+        // Numbers in international format ignore the region
+        let number = phone_number("+49 30 1234567", Some(country_codes::CH)).unwrap();
+        assert_eq!(number.format(PhoneNumberFormat::E164), "+49301234567");
+
+        assert!(phone_number("123", Some(country_codes::CH)).is_err());
+        assert!(phone_number("not a number", Some(country_codes::CH)).is_err());
+    }
+
+    fn address(locality: &str, ty: AddressType, pref: Option<u8>) -> Address {
+        Address {
+            street: "Bahnhofstrasse".to_owned(),
+            number: "1".to_owned(),
+            extended: None,
+            po_box: None,
+            locality: locality.to_owned(),
+            region: None,
+            postal_code: "8001".to_owned(),
+            country: country_codes::CH,
+            ty,
+            label: None,
+            pref,
+        }
+    }
+
+    #[test]
+    fn labels_use_the_most_preferred_address_of_the_given_types() {
+        let contact = Contact {
+            addresses: vec![
+                address("Basel", AddressType::Home, None),
+                address("Bern", AddressType::Work, Some(2)),
+                address("Zürich", AddressType::Home, Some(1)),
+                address("Genf", AddressType::Other, Some(3)),
+            ],
+            ..Contact::default()
+        };
+        let locality = |address_types: &[AddressType]| {
+            contact
+                .label_address(address_types)
+                .map(|address| address.locality.as_str())
+        };
+
+        assert_eq!(locality(&[]), Some("Zürich"));
+        assert_eq!(locality(&[AddressType::Work]), Some("Bern"));
+        assert_eq!(
+            locality(&[AddressType::Work, AddressType::Other]),
+            Some("Bern")
+        );
+        assert_eq!(locality(&[AddressType::Other]), Some("Genf"));
+
+        // Addresses without a preference come last
+        let contact = Contact {
+            addresses: vec![
+                address("Basel", AddressType::Home, None),
+                address("Zürich", AddressType::Home, Some(100)),
+            ],
+            ..Contact::default()
+        };
+        assert_eq!(
+            contact
+                .label_address(&[AddressType::Home])
+                .map(|address| address.locality.as_str()),
+            Some("Zürich")
+        );
+        assert!(contact.label_address(&[AddressType::Work]).is_none());
+    }
+
+    #[test]
+    fn email_addresses_are_validated() {
+        let validate = |address: &str| {
+            EmailAddress {
+                address: address.to_owned(),
+                ty: EmailAddressType::Other,
+                pref: false,
+            }
+            .validate()
+        };
+
+        for address in [
+            "anna@example.com",
+            "anna.muster+contacts@mail.example.ch",
+            "o'brien@example-mail.co.uk",
+            "jürg@bücher.ch",
+        ] {
+            assert!(validate(address).is_ok(), "{address}");
+        }
+
+        for address in [
+            "",
+            "anna",
+            "anna muster@example.com",
+            "@example.com",
+            "anna@bob@example.com",
+            "anna@localhost",
+            "anna@example..com",
+            "anna@.example.com",
+            "anna@example.com.",
+            "anna@exa_mple.com",
+            &format!("{}@example.com", "a".repeat(65)),
+        ] {
+            assert!(validate(address).is_err(), "{address}");
+        }
+        assert!(validate(&format!("{}@example.com", "a".repeat(64))).is_ok());
+    }
+
+    #[test]
+    fn extra_keys_cannot_shadow_vcard_properties() {
+        assert!(validate_extra_key("shoe-size").is_ok());
+        assert!(validate_extra_key("").is_err());
+        assert!(validate_extra_key("shoe size").is_err());
+        for key in [
+            "ablabel",
+            "ABLabel",
+            "socialprofile",
+            "addressbookserver-kind",
+        ] {
+            assert!(validate_extra_key(key).is_err(), "{key}");
+        }
+    }
 }
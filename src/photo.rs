@@ -0,0 +1,126 @@
+//! Contact photos
+//!
+//! Photos are kept in the `photos` directory of the contact store and referenced from the contacts
+//! by their file name. Only common image types are supported, and the type is detected from the
+//! contents of the file rather than from its extension.
+
+use {
+    anyhow::{bail, Context},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The maximum size of a photo in bytes
+///
+/// Photos are embedded in vCards, which many clients refuse to import if they get too large.
+pub const MAX_PHOTO_SIZE: u64 = 512 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageType {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+}
+
+impl ImageType {
+    /// Detects the type of an image from the first bytes of the file.
+    fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageType::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageType::Png)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(ImageType::Gif)
+        } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(ImageType::Webp)
+        } else {
+            None
+        }
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Png => "image/png",
+            ImageType::Gif => "image/gif",
+            ImageType::Webp => "image/webp",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Photo {
+    /// The absolute path of the photo
+    pub path: PathBuf,
+    pub image_type: ImageType,
+    pub data: Vec<u8>,
+}
+
+impl Photo {
+    /// Loads the photo with the given file name from the photos directory of a store.
+    pub fn load<P: AsRef<Path>>(photos_path: P, file_name: &str) -> anyhow::Result<Self> {
+        let path = photos_path.as_ref().join(file_name);
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to open photo {}", path.display()))?;
+
+        let size = fs::metadata(&path)
+            .with_context(|| format!("Failed to open photo {}", path.display()))?
+            .len();
+        if size > MAX_PHOTO_SIZE {
+            bail!(
+                "Photo {} is too large ({size} bytes, at most {MAX_PHOTO_SIZE} bytes are allowed)",
+                path.display()
+            );
+        }
+
+        let data =
+            fs::read(&path).with_context(|| format!("Failed to read photo {}", path.display()))?;
+        let Some(image_type) = ImageType::detect(&data) else {
+            bail!(
+                "Photo {} is not a JPEG, PNG, GIF or WebP image",
+                path.display()
+            );
+        };
+
+        Ok(Photo {
+            path,
+            image_type,
+            data,
+        })
+    }
+
+    /// A `file:` URI pointing to the photo
+    pub fn file_uri(&self) -> String {
+        let mut uri = String::from("file://");
+        for component in self.path.components().skip(1) {
+            uri.push('/');
+            for byte in component.as_os_str().as_encoded_bytes() {
+                match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        uri.push(*byte as char)
+                    }
+                    _ => uri.push_str(&format!("%{byte:02X}")),
+                }
+            }
+        }
+        uri
+    }
+}
+
+/// Checks if `file_name` can be used to reference a photo.
+///
+/// Photos must be located directly in the photos directory, so the file name cannot contain a
+/// path.
+pub fn validate_photo_file_name(file_name: &str) -> anyhow::Result<()> {
+    if file_name.is_empty() {
+        bail!("The file name of a photo cannot be empty");
+    }
+    if file_name.contains(['/', '\\']) || file_name == "." || file_name == ".." {
+        bail!("Photo \"{file_name}\" must be a file name in the photos directory, not a path");
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! Country-aware formatting of postal addresses
+//!
+//! The conventions for writing addresses differ a lot between countries. For example, the house
+//! number comes after the street name in Switzerland, but before it in the United States. This
+//! module knows the conventions of a number of countries and falls back to the most common one
+//! (which is also used in most of Europe) for all others.
+
+use {crate::Address, country_codes::CountryCode};
+
+/// A component of an address that is part of the lines following the street
+#[derive(Clone, Copy, Debug)]
+enum Component {
+    Locality,
+    Region,
+    PostalCode,
+}
+
+/// The conventions for writing the addresses of a country
+#[derive(Clone, Copy, Debug)]
+struct PostalFormat {
+    /// Whether the house number is written before the street name
+    number_first: bool,
+    /// The lines following the street, each consisting of components and the separators that
+    /// precede them. The separator is omitted for the first non-empty component of a line.
+    lines: &'static [&'static [(&'static str, Component)]],
+}
+
+impl PostalFormat {
+    fn of(country: CountryCode) -> Self {
+        use Component::*;
+
+        let (number_first, lines): (bool, &[&[_]]) = match country.alpha2 {
+            "US" => (
+                true,
+                &[&[("", Locality), (", ", Region), (" ", PostalCode)]],
+            ),
+            "AU" | "CA" => (true, &[&[("", Locality), (" ", Region), (" ", PostalCode)]]),
+            "GB" | "ZA" => (true, &[&[("", Locality)], &[("", PostalCode)]]),
+            "IE" => (
+                true,
+                &[&[("", Locality)], &[("", Region), (" ", PostalCode)]],
+            ),
+            "IN" => (
+                true,
+                &[&[("", Locality), (" ", PostalCode)], &[("", Region)]],
+            ),
+            "NZ" | "SG" => (true, &[&[("", Locality), (" ", PostalCode)]]),
+            "FR" | "LU" | "MC" => (true, &[&[("", PostalCode), (" ", Locality)]]),
+            "ES" | "IT" | "MX" => (
+                false,
+                &[&[("", PostalCode), (" ", Locality), (" ", Region)]],
+            ),
+            "BR" => (
+                false,
+                &[&[("", Locality), ("-", Region)], &[("", PostalCode)]],
+            ),
+            "RU" | "UA" => (
+                false,
+                &[&[("", Locality)], &[("", Region)], &[("", PostalCode)]],
+            ),
+            _ => (
+                false,
+                &[&[("", PostalCode), (" ", Locality)], &[("", Region)]],
+            ),
+        };
+
+        Self {
+            number_first,
+            lines,
+        }
+    }
+}
+
+impl Address {
+    /// The street name and the house number, in the order used in the country of the address
+    pub fn street_line(&self) -> String {
+        let (first, second) = if PostalFormat::of(self.country).number_first {
+            (&self.number, &self.street)
+        } else {
+            (&self.street, &self.number)
+        };
+        [first.trim(), second.trim()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Splits a street line as written by [`Address::street_line`] into the street name and the
+    /// house number.
+    ///
+    /// The house number is the first or last word of the line, depending on the country, and has
+    /// to start with a digit. Otherwise the whole line is taken as the street name.
+    pub fn split_street_line(street_line: &str, country: CountryCode) -> (String, String) {
+        let street_line = street_line.trim();
+        let split = if PostalFormat::of(country).number_first {
+            street_line
+                .split_once(' ')
+                .map(|(number, street)| (street, number))
+        } else {
+            street_line.rsplit_once(' ')
+        };
+        match split {
+            Some((street, number)) if number.starts_with(|c: char| c.is_ascii_digit()) => {
+                (street.trim().to_owned(), number.to_owned())
+            }
+            _ => (street_line.to_owned(), String::new()),
+        }
+    }
+
+    /// Formats the address the way it would be written on an envelope, including the country.
+    pub fn postal_lines(&self) -> Vec<String> {
+        let format = PostalFormat::of(self.country);
+
+        let mut lines = vec![
+            self.street_line(),
+            self.extended.clone().unwrap_or_default(),
+            self.po_box.clone().unwrap_or_default(),
+        ];
+        for components in format.lines {
+            let mut line = String::new();
+            for &(separator, component) in *components {
+                let value = match component {
+                    Component::Locality => self.locality.as_str(),
+                    Component::Region => self.region.as_deref().unwrap_or_default(),
+                    Component::PostalCode => self.postal_code.as_str(),
+                }
+                .trim();
+                if value.is_empty() {
+                    continue;
+                }
+                if !line.is_empty() {
+                    line.push_str(separator);
+                }
+                line.push_str(value);
+            }
+            lines.push(line);
+        }
+        lines.push(self.country.name.to_owned());
+
+        lines.retain(|line| !line.trim().is_empty());
+        lines
+    }
+
+    /// Formats the address for a letter sent from the given country.
+    ///
+    /// As recommended by the Universal Postal Union, the country is omitted for domestic mail and
+    /// written in capital letters for international mail.
+    pub fn envelope_lines(&self, origin: Option<CountryCode>) -> Vec<String> {
+        let mut lines = self.postal_lines();
+        lines.pop();
+        if origin.map(|origin| origin.alpha2) != Some(self.country.alpha2) {
+            lines.push(self.country.name.to_uppercase());
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::AddressType};
+
+    fn address(
+        street: &str,
+        number: &str,
+        locality: &str,
+        region: Option<&str>,
+        postal_code: &str,
+        country: CountryCode,
+    ) -> Address {
+        Address {
+            street: street.to_owned(),
+            number: number.to_owned(),
+            extended: None,
+            po_box: None,
+            locality: locality.to_owned(),
+            region: region.map(str::to_owned),
+            postal_code: postal_code.to_owned(),
+            country,
+            ty: AddressType::Home,
+            label: None,
+            pref: None,
+        }
+    }
+
+    #[test]
+    fn addresses_are_formatted_by_country() {
+        let swiss = address(
+            "Bahnhofstrasse",
+            "1",
+            "Zürich",
+            None,
+            "8001",
+            country_codes::CH,
+        );
+        assert_eq!(
+            swiss.postal_lines(),
+            ["Bahnhofstrasse 1", "8001 Zürich", "Switzerland"]
+        );
+
+        let american = address(
+            "Pennsylvania Avenue NW",
+            "1600",
+            "Washington",
+            Some("DC"),
+            "20500",
+            country_codes::US,
+        );
+        assert_eq!(
+            american.postal_lines()[..2],
+            ["1600 Pennsylvania Avenue NW", "Washington, DC 20500"]
+        );
+
+        let british = address(
+            "Downing Street",
+            "10",
+            "London",
+            None,
+            "SW1A 2AA",
+            country_codes::GB,
+        );
+        assert_eq!(
+            british.postal_lines()[..3],
+            ["10 Downing Street", "London", "SW1A 2AA"]
+        );
+
+        let french = address(
+            "rue de Rivoli",
+            "99",
+            "Paris",
+            None,
+            "75001",
+            country_codes::FR,
+        );
+        assert_eq!(
+            french.postal_lines()[..2],
+            ["99 rue de Rivoli", "75001 Paris"]
+        );
+    }
+
+    #[test]
+    fn the_country_is_only_written_on_international_mail() {
+        let swiss = address(
+            "Bahnhofstrasse",
+            "1",
+            "Zürich",
+            None,
+            "8001",
+            country_codes::CH,
+        );
+        assert_eq!(
+            swiss.envelope_lines(Some(country_codes::CH)),
+            ["Bahnhofstrasse 1", "8001 Zürich"]
+        );
+        assert_eq!(
+            swiss.envelope_lines(Some(country_codes::US)),
+            ["Bahnhofstrasse 1", "8001 Zürich", "SWITZERLAND"]
+        );
+    }
+
+    #[test]
+    fn street_lines_are_split_by_country() {
+        assert_eq!(
+            Address::split_street_line("Bahnhofstrasse 12a", country_codes::CH),
+            ("Bahnhofstrasse".to_owned(), "12a".to_owned())
+        );
+        assert_eq!(
+            Address::split_street_line("1600 Pennsylvania Avenue", country_codes::US),
+            ("Pennsylvania Avenue".to_owned(), "1600".to_owned())
+        );
+        assert_eq!(
+            Address::split_street_line("Am Markt", country_codes::DE),
+            ("Am Markt".to_owned(), String::new())
+        );
+    }
+}
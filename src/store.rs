@@ -1,29 +1,464 @@
+//! Storage of the contacts
+//!
+//! A [`ContactStore`] is a directory containing the configuration, the photos and the contacts of
+//! a user. Where exactly the contacts are kept is up to a [`Backend`], which is chosen by the
+//! configuration of the store. The default backend can keep the contacts encrypted, which is
+//! detected when loading the store.
+
+mod encryption;
+mod json_file;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod vdir;
+
 use {
-    crate::{json, Contact},
-    anyhow::Context,
-    std::{fs::File, io::BufReader, path::Path},
+    crate::{config::Config, json, photo::Photo, Contact},
+    anyhow::{anyhow, bail, Context},
+    encryption::Keys,
+    json_file::JsonFileBackend,
+    std::{
+        fmt::{self, Display},
+        fs::{self, File},
+        io::Write,
+        path::{Path, PathBuf},
+    },
+    vdir::VdirBackend,
 };
 
+/// A place where the contacts of a store are kept
+///
+/// Contacts are identified by their UIDs. Changes only become persistent when they are saved.
+/// Backends may keep all contacts in memory, or read and write single contacts as needed.
+pub trait Backend {
+    /// Read all contacts, skipping invalid ones
+    ///
+    /// Diagnostics describing why contacts were skipped are returned along with the contacts.
+    fn load(&mut self) -> anyhow::Result<(Vec<Contact>, Vec<Diagnostic>)>;
+
+    /// The contact with the given UID
+    fn get(&mut self, uid: &str) -> anyhow::Result<Option<Contact>>;
+
+    /// Add a contact. Its UID must not be used by another contact.
+    fn insert(&mut self, contact: Contact) -> anyhow::Result<()>;
+
+    /// Replace the contact with the given UID. The UID of the contact itself may differ.
+    fn update(&mut self, uid: &str, contact: Contact) -> anyhow::Result<()>;
+
+    /// Remove the contact with the given UID and return it
+    fn delete(&mut self, uid: &str) -> anyhow::Result<Contact>;
+
+    /// Make all changes persistent
+    fn save(&mut self) -> anyhow::Result<()>;
+
+    /// Find contacts with a full-text index, see [`ContactStore::search`]
+    ///
+    /// The UIDs of the matching contacts are returned, ordered by relevance. Backends without an
+    /// index return `None`, in which case all contacts are searched.
+    fn search(&mut self, _query: &str) -> anyhow::Result<Option<Vec<String>>> {
+        Ok(None)
+    }
+}
+
+/// The contacts of a backend keeping all of them in memory, which are written all at once
+#[derive(Debug, Default)]
+struct ContactList {
+    contacts: Vec<Contact>,
+    /// Whether invalid contacts were skipped when loading, which would be lost when saving
+    incomplete: bool,
+}
+
+impl ContactList {
+    fn new(contacts: Vec<Contact>, diagnostics: &[Diagnostic]) -> Self {
+        ContactList {
+            contacts,
+            incomplete: !diagnostics.is_empty(),
+        }
+    }
+
+    fn get(&self, uid: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|contact| contact.uid() == uid)
+    }
+
+    fn insert(&mut self, contact: Contact) -> anyhow::Result<()> {
+        self.check_complete()?;
+        if self.get(contact.uid()).is_some() {
+            bail!(
+                "A contact with the UID \"{}\" already exists",
+                contact.uid()
+            );
+        }
+        self.contacts.push(contact);
+        Ok(())
+    }
+
+    fn update(&mut self, uid: &str, contact: Contact) -> anyhow::Result<()> {
+        self.check_complete()?;
+        let new_uid = contact.uid();
+        if new_uid != uid && self.get(new_uid).is_some() {
+            bail!("A contact with the UID \"{new_uid}\" already exists");
+        }
+        let existing = self
+            .contacts
+            .iter_mut()
+            .find(|existing| existing.uid() == uid)
+            .with_context(|| format!("No contact has the UID \"{uid}\""))?;
+        *existing = contact;
+        Ok(())
+    }
+
+    fn delete(&mut self, uid: &str) -> anyhow::Result<Contact> {
+        self.check_complete()?;
+        let index = self
+            .contacts
+            .iter()
+            .position(|contact| contact.uid() == uid)
+            .with_context(|| format!("No contact has the UID \"{uid}\""))?;
+        Ok(self.contacts.remove(index))
+    }
+
+    fn check_complete(&self) -> anyhow::Result<()> {
+        if self.incomplete {
+            bail!("The contact store cannot be modified, as invalid contacts would be lost");
+        }
+        Ok(())
+    }
+}
+
+/// Write a file by first writing a temporary file which then replaces it, so that the file is never
+/// left half-written
+fn write_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    File::create(&temp_path)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Fails with the first diagnostic, if there is any
+fn check_diagnostics(diagnostics: Vec<Diagnostic>) -> anyhow::Result<()> {
+    match diagnostics.into_iter().next() {
+        Some(diagnostic) => Err(anyhow!("{diagnostic}")).context("Failed to parse contact store"),
+        None => Ok(()),
+    }
+}
+
+/// The texts of a contact that are searched: its name, email addresses, notes and addresses
+fn search_fields(contact: &Contact) -> [String; 4] {
+    let name = &contact.name;
+    [
+        [name.to_string()]
+            .into_iter()
+            .chain(name.nickname.clone())
+            .collect::<Vec<_>>()
+            .join(" "),
+        contact
+            .email_addresses
+            .iter()
+            .map(|email_address| email_address.address.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        contact.notes.clone().unwrap_or_default(),
+        contact
+            .addresses
+            .iter()
+            .map(|address| address.postal_lines().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    ]
+}
+
+/// A change of the contacts in a store
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The changed contact. For updates, this is the new version.
+    pub contact: Contact,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChangeKind {
+    Inserted,
+    Updated,
+    Deleted,
+}
+
+/// A problem found in a contact store, along with the file it was found in
 #[derive(Debug)]
+pub enum Diagnostic {
+    Json(PathBuf, json::Diagnostic),
+    Vcard(PathBuf, anyhow::Error),
+    /// A problem with the JSON representation of the contact with the given UID in a database
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf, String, json::Diagnostic),
+    /// A photo that cannot be exported, along with the name of its contact
+    Photo(String, anyhow::Error),
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::Json(path, diagnostic) => write!(f, "{}:{diagnostic}", path.display()),
+            Diagnostic::Vcard(path, error) => write!(f, "{}: {error:#}", path.display()),
+            #[cfg(feature = "sqlite")]
+            Diagnostic::Sqlite(path, uid, diagnostic) => {
+                write!(f, "{}: {uid}:{diagnostic}", path.display())
+            }
+            Diagnostic::Photo(name, error) => write!(f, "Photo of {name}: {error:#}"),
+        }
+    }
+}
+
 pub struct ContactStore {
-    contacts: Vec<Contact>,
+    backend: Box<dyn Backend>,
+    /// All contacts, once they are needed
+    contacts: Option<Vec<Contact>>,
+    /// Changes that have not been saved yet
+    changes: Vec<Change>,
+    listeners: Vec<Listener>,
 }
 
+/// A function notified about saved changes, see [`ContactStore::on_change`]
+type Listener = Box<dyn FnMut(&Change)>;
+
 impl ContactStore {
     /// Initialize a store located at the given path
-    pub fn from_path<P: AsRef<Path>>(store_path: P) -> anyhow::Result<Self> {
-        let contacts_path = store_path.as_ref().join("contacts.json");
-        let contacts_file = File::open(&contacts_path).with_context(|| {
-            format!(
-                "Failed to open contact store at {}",
-                contacts_path.display()
-            )
-        })?;
-        let contacts = json::contacts_from_json(BufReader::new(contacts_file))?;
-        Ok(ContactStore { contacts })
-    }
-
-    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
-        self.contacts.iter()
+    ///
+    /// Contacts are only read when they are needed, and reading an invalid contact is an error.
+    pub fn from_path<P: AsRef<Path>>(store_path: P, config: &Config) -> anyhow::Result<Self> {
+        Ok(ContactStore {
+            backend: Self::backend(store_path, config)?,
+            contacts: None,
+            changes: Vec::new(),
+            listeners: Vec::new(),
+        })
+    }
+
+    /// Initialize a store located at the given path, skipping all invalid contacts
+    ///
+    /// Diagnostics describing why contacts were skipped are returned along with the store.
+    pub fn from_path_lenient<P: AsRef<Path>>(
+        store_path: P,
+        config: &Config,
+    ) -> anyhow::Result<(Self, Vec<Diagnostic>)> {
+        let mut store = Self::from_path(store_path, config)?;
+        let (contacts, diagnostics) = store.backend.load()?;
+        store.contacts = Some(contacts);
+        Ok((store, diagnostics))
+    }
+
+    /// Check the store located at the given path for problems, including photos that cannot be
+    /// exported
+    pub fn validate<P: AsRef<Path>>(
+        store_path: P,
+        config: &Config,
+    ) -> anyhow::Result<Vec<Diagnostic>> {
+        let (contacts, mut diagnostics) = Self::backend(&store_path, config)?.load()?;
+        let photos_path = Self::photos_path(store_path);
+        for contact in &contacts {
+            if let Some(file_name) = &contact.photo {
+                if let Err(error) = Photo::load(&photos_path, file_name) {
+                    diagnostics.push(Diagnostic::Photo(contact.name.to_string(), error));
+                }
+            }
+        }
+        Ok(diagnostics)
+    }
+
+    /// The backend configured for the store located at the given path
+    fn backend<P: AsRef<Path>>(store_path: P, config: &Config) -> anyhow::Result<Box<dyn Backend>> {
+        let default_region = config.default_region();
+        Ok(match (config.vdir(), config.sqlite()) {
+            (Some(_), Some(_)) => bail!("Only one of the options vdir and sqlite can be set"),
+            (Some(vdir_path), None) => {
+                Box::new(VdirBackend::new(store_path, vdir_path, default_region))
+            }
+            #[cfg(feature = "sqlite")]
+            (None, Some(database_path)) => Box::new(sqlite::SqliteBackend::open(
+                store_path.as_ref().join(database_path),
+                default_region,
+            )?),
+            #[cfg(not(feature = "sqlite"))]
+            (None, Some(_)) => {
+                bail!("SQLite stores require tactful to be built with the sqlite feature")
+            }
+            (None, None) => Box::new(JsonFileBackend::new(store_path, config)?),
+        })
+    }
+
+    /// The path of the directory containing the photos of the store located at the given path
+    pub fn photos_path<P: AsRef<Path>>(store_path: P) -> PathBuf {
+        store_path.as_ref().join("photos")
+    }
+
+    /// All contacts of the store, which are read on first use
+    pub fn contacts(&mut self) -> anyhow::Result<&[Contact]> {
+        if self.contacts.is_none() {
+            let (contacts, diagnostics) = self.backend.load()?;
+            check_diagnostics(diagnostics)?;
+            self.contacts = Some(contacts);
+        }
+        Ok(self.contacts.as_deref().expect("contacts are loaded"))
+    }
+
+    /// The contact with the given UID
+    pub fn get(&mut self, uid: &str) -> anyhow::Result<Option<Contact>> {
+        match &self.contacts {
+            Some(contacts) => Ok(contacts
+                .iter()
+                .find(|contact| contact.uid() == uid)
+                .cloned()),
+            None => self.backend.get(uid),
+        }
+    }
+
+    /// Find the contacts whose name, email addresses, notes or addresses contain all words of the
+    /// query (case-insensitive), ordered by relevance
+    ///
+    /// Backends with a full-text index may only match words at the start of words.
+    pub fn search(&mut self, query: &str) -> anyhow::Result<Vec<Contact>> {
+        let words = query.to_lowercase();
+        let words = words.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            bail!("The search query is empty");
+        }
+
+        if let Some(uids) = self.backend.search(query)? {
+            let mut contacts = Vec::with_capacity(uids.len());
+            for uid in uids {
+                // Contacts skipped in lenient mode are not found
+                contacts.extend(self.get(&uid)?);
+            }
+            return Ok(contacts);
+        }
+        Ok(self
+            .contacts()?
+            .iter()
+            .filter(|contact| {
+                let fields = search_fields(contact).join("\n").to_lowercase();
+                words.iter().all(|word| fields.contains(word))
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Add a contact to the store
+    pub fn insert(&mut self, contact: Contact) -> anyhow::Result<()> {
+        self.backend.insert(contact.clone())?;
+        if let Some(contacts) = &mut self.contacts {
+            contacts.push(contact.clone());
+        }
+        self.changes.push(Change {
+            kind: ChangeKind::Inserted,
+            contact,
+        });
+        Ok(())
+    }
+
+    /// Replace the contact with the given UID
+    pub fn update(&mut self, uid: &str, contact: Contact) -> anyhow::Result<()> {
+        self.backend.update(uid, contact.clone())?;
+        if let Some(contacts) = &mut self.contacts {
+            if let Some(existing) = contacts.iter_mut().find(|existing| existing.uid() == uid) {
+                *existing = contact.clone();
+            }
+        }
+        self.changes.push(Change {
+            kind: ChangeKind::Updated,
+            contact,
+        });
+        Ok(())
+    }
+
+    /// Remove the contact with the given UID from the store
+    pub fn delete(&mut self, uid: &str) -> anyhow::Result<Contact> {
+        let contact = self.backend.delete(uid)?;
+        if let Some(contacts) = &mut self.contacts {
+            contacts.retain(|existing| existing.uid() != uid);
+        }
+        self.changes.push(Change {
+            kind: ChangeKind::Deleted,
+            contact: contact.clone(),
+        });
+        Ok(contact)
+    }
+
+    /// Encrypt the contacts of the store located at the given path with the configured recipients
+    /// or, if there are none, a passphrase
+    ///
+    /// Encrypted stores can be encrypted again, e.g. to change the passphrase.
+    pub fn encrypt<P: AsRef<Path>>(store_path: P, config: &Config) -> anyhow::Result<()> {
+        Self::check_encryptable(config)?;
+        let store_path = store_path.as_ref();
+        encryption::encrypt_store(store_path, &Keys::from_config(store_path, config))
+    }
+
+    /// Store the contacts of the encrypted store located at the given path unencrypted again
+    pub fn decrypt<P: AsRef<Path>>(store_path: P, config: &Config) -> anyhow::Result<()> {
+        Self::check_encryptable(config)?;
+        let store_path = store_path.as_ref();
+        encryption::decrypt_store(store_path, &Keys::from_config(store_path, config))
+    }
+
+    fn check_encryptable(config: &Config) -> anyhow::Result<()> {
+        if config.vdir().is_some() || config.sqlite().is_some() {
+            bail!("Only contact stores keeping their contacts in contacts.json can be encrypted");
+        }
+        Ok(())
+    }
+
+    /// Register a function that is called for every change once it has been saved
+    pub fn on_change<F: FnMut(&Change) + 'static>(&mut self, listener: F) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Write the changes back to the store and notify the listeners about them
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        self.backend.save()?;
+        for change in self.changes.drain(..) {
+            for listener in &mut self.listeners {
+                listener(&change);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient_stores_skip_invalid_contacts_and_cannot_be_saved() {
+        let store_path = tempfile::tempdir().unwrap();
+        let contacts_path = store_path.path().join(json_file::FILE_NAME);
+        let input = r#"[
+            {"uid": "urn:uuid:a", "name": {"first": "Anna"}},
+            {"uid": "urn:uuid:b", "name": {"first": "Bob"}, "email": [{"address": "bob"}]}
+        ]"#;
+        fs::write(&contacts_path, input).unwrap();
+
+        assert!(
+            ContactStore::from_path(store_path.path(), &Config::default())
+                .unwrap()
+                .contacts()
+                .is_err()
+        );
+
+        let (mut store, diagnostics) =
+            ContactStore::from_path_lenient(store_path.path(), &Config::default()).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        let names = store
+            .contacts()
+            .unwrap()
+            .iter()
+            .map(|contact| contact.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Anna"]);
+
+        assert!(store.delete("urn:uuid:a").is_err());
+        assert!(store.save().is_err());
+        assert_eq!(fs::read_to_string(&contacts_path).unwrap(), input);
     }
 }
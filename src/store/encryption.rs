@@ -0,0 +1,361 @@
+//! Encryption of the contacts file with age
+//!
+//! Encrypted stores keep their contacts in the ASCII-armored age file `contacts.json.age` instead
+//! of `contacts.json`, so they can be committed to a repository without revealing them. The file
+//! is either encrypted with a passphrase or to age recipients (X25519 public keys), in which case
+//! an identity file with a matching private key is needed to decrypt it.
+//!
+//! The passphrase is never taken from the store itself, as the store may be shared with others.
+
+use {
+    super::{json_file, write_file},
+    crate::config::Config,
+    age::{
+        armor::{ArmoredReader, ArmoredWriter, Format},
+        secrecy::SecretString,
+        Decryptor, Encryptor, IdentityFile, Recipient,
+    },
+    anyhow::{anyhow, bail, Context},
+    std::{
+        env,
+        fs::{self, File},
+        io::{self, BufReader, Read, Write},
+        iter,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
+};
+
+/// The name of the contacts file of encrypted stores
+pub const FILE_NAME: &str = "contacts.json.age";
+
+/// The environment variable which may contain the passphrase of an encrypted store
+const PASSPHRASE_VARIABLE: &str = "TACTFUL_PASSPHRASE";
+
+/// The environment variable which may contain a shell command printing the passphrase of an
+/// encrypted store
+const PASSPHRASE_COMMAND_VARIABLE: &str = "TACTFUL_PASSPHRASE_COMMAND";
+
+/// The configured ways to obtain the keys of an encrypted store
+#[derive(Debug)]
+pub struct Keys {
+    identity: Option<PathBuf>,
+    recipients: Vec<String>,
+}
+
+impl Keys {
+    pub fn from_config<P: AsRef<Path>>(store_path: P, config: &Config) -> Self {
+        Keys {
+            identity: config
+                .identity()
+                .map(|identity| store_path.as_ref().join(identity)),
+            recipients: config.recipients().to_vec(),
+        }
+    }
+
+    /// Read a passphrase from the environment variable `TACTFUL_PASSPHRASE`, the command in
+    /// `TACTFUL_PASSPHRASE_COMMAND` or, if neither is set, a prompt. If `repeat` is set, a
+    /// prompted passphrase has to be entered twice.
+    fn passphrase(&self, prompt: &str, repeat: bool) -> anyhow::Result<SecretString> {
+        let passphrase = if let Ok(passphrase) = env::var(PASSPHRASE_VARIABLE) {
+            passphrase
+        } else if let Ok(command) = env::var(PASSPHRASE_COMMAND_VARIABLE) {
+            passphrase_from_command(&command)?
+        } else {
+            let passphrase =
+                rpassword::prompt_password(prompt).context("Failed to read the passphrase")?;
+            if repeat
+                && rpassword::prompt_password("Repeat the passphrase: ")
+                    .context("Failed to read the passphrase")?
+                    != passphrase
+            {
+                bail!("The passphrases do not match");
+            }
+            passphrase
+        };
+        if passphrase.is_empty() {
+            bail!("The passphrase is empty");
+        }
+        Ok(passphrase.into())
+    }
+
+    fn recipients(&self) -> anyhow::Result<Vec<Box<dyn Recipient + Send>>> {
+        self.recipients
+            .iter()
+            .map(|recipient| {
+                recipient
+                    .parse::<age::x25519::Recipient>()
+                    .map(|recipient| Box::new(recipient) as _)
+                    .map_err(|error| anyhow!("Invalid recipient \"{recipient}\": {error}"))
+            })
+            .collect()
+    }
+}
+
+/// How the contacts file of a store is encrypted
+pub enum Encryption {
+    Passphrase(SecretString),
+    Recipients(Vec<Box<dyn Recipient + Send>>),
+}
+
+impl Encryption {
+    /// The encryption for newly encrypting a store: the configured recipients or, if there are
+    /// none, a new passphrase
+    pub fn new(keys: &Keys) -> anyhow::Result<Self> {
+        if keys.recipients.is_empty() {
+            keys.passphrase("New passphrase: ", true)
+                .map(Encryption::Passphrase)
+        } else {
+            keys.recipients().map(Encryption::Recipients)
+        }
+    }
+
+    /// Decrypt the contents of the encrypted contacts file at the given path
+    ///
+    /// The encryption of the file is returned along with the plaintext, so that the file can be
+    /// encrypted the same way when it is saved. Files encrypted to recipients are encrypted to the
+    /// configured recipients or, if there are none, to the recipients of the identity file.
+    pub fn decrypt(path: &Path, ciphertext: &[u8], keys: &Keys) -> anyhow::Result<(Vec<u8>, Self)> {
+        let error_context = || format!("Failed to decrypt contact store at {}", path.display());
+        let decryptor =
+            Decryptor::new_buffered(ArmoredReader::new(ciphertext)).with_context(error_context)?;
+
+        let mut plaintext = Vec::new();
+        let encryption = if decryptor.is_scrypt() {
+            let passphrase =
+                keys.passphrase(&format!("Passphrase for {}: ", path.display()), false)?;
+            let identity = age::scrypt::Identity::new(passphrase.clone());
+            decryptor
+                .decrypt(iter::once(&identity as _))
+                .with_context(error_context)?
+                .read_to_end(&mut plaintext)
+                .with_context(error_context)?;
+            Encryption::Passphrase(passphrase)
+        } else {
+            let identity_path = keys.identity.as_ref().with_context(|| {
+                format!(
+                    "{} is encrypted to recipients, but no identity file is configured",
+                    path.display()
+                )
+            })?;
+            let identity_file = File::open(identity_path)
+                .map(BufReader::new)
+                .and_then(IdentityFile::from_buffer)
+                .with_context(|| {
+                    format!("Failed to read identity file {}", identity_path.display())
+                })?;
+            let recipients = if keys.recipients.is_empty() {
+                identity_file.to_recipients()?
+            } else {
+                keys.recipients()?
+            };
+            let identities = identity_file.into_identities()?;
+            decryptor
+                .decrypt(identities.iter().map(|identity| identity.as_ref()))
+                .with_context(error_context)?
+                .read_to_end(&mut plaintext)
+                .with_context(error_context)?;
+            Encryption::Recipients(recipients)
+        };
+
+        Ok((plaintext, encryption))
+    }
+
+    /// Encrypt the contents of a contacts file
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let encryptor = match self {
+            Encryption::Passphrase(passphrase) => {
+                Encryptor::with_user_passphrase(passphrase.clone())
+            }
+            Encryption::Recipients(recipients) => Encryptor::with_recipients(
+                recipients.iter().map(|recipient| recipient.as_ref() as _),
+            )?,
+        };
+
+        let mut ciphertext = Vec::new();
+        let mut writer = encryptor.wrap_output(ArmoredWriter::wrap_output(
+            &mut ciphertext,
+            Format::AsciiArmor,
+        )?)?;
+        writer.write_all(plaintext)?;
+        writer.finish()?.finish()?;
+        Ok(ciphertext)
+    }
+}
+
+/// The encryption layer of the contacts file of an encrypted store
+pub struct EncryptedFile {
+    keys: Keys,
+    /// How the file was encrypted, so that it is encrypted the same way when it is written back
+    encryption: Option<Encryption>,
+}
+
+impl EncryptedFile {
+    pub fn new(keys: Keys) -> Self {
+        EncryptedFile {
+            keys,
+            encryption: None,
+        }
+    }
+
+    pub fn decrypt(&mut self, path: &Path, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (plaintext, encryption) = Encryption::decrypt(path, ciphertext, &self.keys)?;
+        self.encryption = Some(encryption);
+        Ok(plaintext)
+    }
+
+    /// Encrypt the contents of the file the same way as it was encrypted when it was read
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.encryption
+            .as_ref()
+            .expect("the file is read before it is written")
+            .encrypt(plaintext)
+    }
+}
+
+/// Encrypt the contacts file of the store located at `store_path` with the configured recipients
+/// or, if there are none, a new passphrase
+///
+/// Encrypted stores are encrypted again, e.g. to change the passphrase. The unencrypted file is
+/// only removed once the encrypted one has been written.
+pub fn encrypt_store(store_path: &Path, keys: &Keys) -> anyhow::Result<()> {
+    let plain_path = store_path.join(json_file::FILE_NAME);
+    let encrypted_path = store_path.join(FILE_NAME);
+    check_not_both(store_path, &plain_path, &encrypted_path)?;
+    let plaintext = if encrypted_path.exists() {
+        let ciphertext = fs::read(&encrypted_path)
+            .with_context(|| format!("Failed to read {}", encrypted_path.display()))?;
+        Encryption::decrypt(&encrypted_path, &ciphertext, keys)?.0
+    } else {
+        fs::read(&plain_path).with_context(|| format!("Failed to read {}", plain_path.display()))?
+    };
+
+    let ciphertext = Encryption::new(keys)?.encrypt(&plaintext)?;
+    write_file(&encrypted_path, &ciphertext)?;
+    remove_file(&plain_path)
+}
+
+/// Store the contacts of the encrypted store located at `store_path` unencrypted again
+pub fn decrypt_store(store_path: &Path, keys: &Keys) -> anyhow::Result<()> {
+    let plain_path = store_path.join(json_file::FILE_NAME);
+    let encrypted_path = store_path.join(FILE_NAME);
+    if !encrypted_path.exists() {
+        bail!(
+            "The contact store at {} is not encrypted",
+            store_path.display()
+        );
+    }
+    check_not_both(store_path, &plain_path, &encrypted_path)?;
+
+    let ciphertext = fs::read(&encrypted_path)
+        .with_context(|| format!("Failed to read {}", encrypted_path.display()))?;
+    let (plaintext, _) = Encryption::decrypt(&encrypted_path, &ciphertext, keys)?;
+    write_file(&plain_path, &plaintext)?;
+    remove_file(&encrypted_path)
+}
+
+/// Fails if the store contains both an unencrypted and an encrypted contacts file, as it is
+/// unclear which one is newer
+fn check_not_both(
+    store_path: &Path,
+    plain_path: &Path,
+    encrypted_path: &Path,
+) -> anyhow::Result<()> {
+    if plain_path.exists() && encrypted_path.exists() {
+        bail!(
+            "The contact store at {} contains both {} and {FILE_NAME}",
+            store_path.display(),
+            json_file::FILE_NAME
+        );
+    }
+    Ok(())
+}
+
+/// Remove a file if it exists
+fn remove_file(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            Err(error).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Run a command printing a passphrase with `sh -c`. Only the first line of its output is used.
+fn passphrase_from_command(command: &str) -> anyhow::Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run the passphrase command \"{command}\""))?;
+    if !output.status.success() {
+        bail!(
+            "The passphrase command \"{command}\" failed with {}",
+            output.status
+        );
+    }
+    let output = String::from_utf8(output.stdout)
+        .with_context(|| format!("The passphrase command \"{command}\" printed invalid UTF-8"))?;
+    Ok(output.lines().next().unwrap_or_default().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passphrase_keys() -> Keys {
+        env::set_var(PASSPHRASE_VARIABLE, "correct horse battery staple");
+        Keys {
+            identity: None,
+            recipients: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn contents_survive_a_round_trip_with_a_passphrase() {
+        let keys = passphrase_keys();
+        let ciphertext = Encryption::new(&keys).unwrap().encrypt(b"[]").unwrap();
+        assert!(ciphertext.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        let (plaintext, encryption) =
+            Encryption::decrypt(Path::new(FILE_NAME), &ciphertext, &keys).unwrap();
+        assert_eq!(plaintext, b"[]");
+        assert!(matches!(encryption, Encryption::Passphrase(_)));
+    }
+
+    #[test]
+    fn stores_are_encrypted_and_decrypted() {
+        let keys = passphrase_keys();
+        let store = tempfile::tempdir().unwrap();
+        let plain_path = store.path().join(json_file::FILE_NAME);
+        let encrypted_path = store.path().join(FILE_NAME);
+        fs::write(&plain_path, "[]").unwrap();
+
+        encrypt_store(store.path(), &keys).unwrap();
+        assert!(!plain_path.exists());
+        assert!(encrypted_path.exists());
+
+        decrypt_store(store.path(), &keys).unwrap();
+        assert_eq!(fs::read_to_string(&plain_path).unwrap(), "[]");
+        assert!(!encrypted_path.exists());
+        assert!(decrypt_store(store.path(), &keys).is_err());
+    }
+
+    #[test]
+    fn stores_with_both_files_are_left_alone() {
+        let keys = passphrase_keys();
+        let store = tempfile::tempdir().unwrap();
+        let plain_path = store.path().join(json_file::FILE_NAME);
+        let encrypted_path = store.path().join(FILE_NAME);
+        fs::write(&plain_path, "[]").unwrap();
+        let ciphertext = Encryption::new(&keys).unwrap().encrypt(b"[{}]").unwrap();
+        fs::write(&encrypted_path, &ciphertext).unwrap();
+
+        assert!(encrypt_store(store.path(), &keys).is_err());
+        assert!(decrypt_store(store.path(), &keys).is_err());
+        assert_eq!(fs::read_to_string(&plain_path).unwrap(), "[]");
+        assert_eq!(fs::read(&encrypted_path).unwrap(), ciphertext);
+    }
+}
@@ -0,0 +1,154 @@
+//! The default backend, keeping all contacts in the file `contacts.json` of the store
+//!
+//! Encrypted stores keep the same JSON in the file `contacts.json.age` instead, which is decrypted
+//! when reading it and encrypted again when writing it, see [`encryption`](super::encryption).
+
+use {
+    super::{
+        check_diagnostics,
+        encryption::{self, EncryptedFile, Keys},
+        write_file, Backend, ContactList, Diagnostic,
+    },
+    crate::{config::Config, json, Contact},
+    anyhow::{bail, Context},
+    country_codes::CountryCode,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The name of the contacts file of unencrypted stores
+pub const FILE_NAME: &str = "contacts.json";
+
+pub struct JsonFileBackend {
+    /// The path of the file containing the contacts
+    path: PathBuf,
+    default_region: Option<CountryCode>,
+    /// The contacts, once they have been read
+    contacts: Option<ContactList>,
+    /// Decrypts and encrypts the file if the store is encrypted
+    encryption: Option<EncryptedFile>,
+}
+
+impl JsonFileBackend {
+    pub fn new<P: AsRef<Path>>(store_path: P, config: &Config) -> anyhow::Result<Self> {
+        let store_path = store_path.as_ref();
+        let plain_path = store_path.join(FILE_NAME);
+        let encrypted_path = store_path.join(encryption::FILE_NAME);
+        let (path, encryption) = match (plain_path.exists(), encrypted_path.exists()) {
+            (true, true) => bail!(
+                "The contact store at {} contains both {FILE_NAME} and {}",
+                store_path.display(),
+                encryption::FILE_NAME
+            ),
+            (false, true) => (
+                encrypted_path,
+                Some(EncryptedFile::new(Keys::from_config(store_path, config))),
+            ),
+            _ => (plain_path, None),
+        };
+        Ok(JsonFileBackend {
+            path,
+            default_region: config.default_region(),
+            contacts: None,
+            encryption,
+        })
+    }
+
+    /// The contacts, which are read on first use. Invalid contacts are an error.
+    fn contact_list(&mut self) -> anyhow::Result<&mut ContactList> {
+        if self.contacts.is_none() {
+            let (_, diagnostics) = self.load()?;
+            check_diagnostics(diagnostics)?;
+        }
+        Ok(self.contacts.as_mut().expect("contacts are loaded"))
+    }
+}
+
+impl Backend for JsonFileBackend {
+    fn load(&mut self) -> anyhow::Result<(Vec<Contact>, Vec<Diagnostic>)> {
+        let error_context = || format!("Failed to open contact store at {}", self.path.display());
+        let mut contents = fs::read(&self.path).with_context(error_context)?;
+        if let Some(encryption) = &mut self.encryption {
+            contents = encryption.decrypt(&self.path, &contents)?;
+        }
+        let contents = String::from_utf8(contents).with_context(error_context)?;
+        let (contacts, diagnostics) =
+            json::contacts_from_json_lenient(&contents, self.default_region);
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| Diagnostic::Json(self.path.clone(), diagnostic))
+            .collect::<Vec<_>>();
+        self.contacts = Some(ContactList::new(contacts.clone(), &diagnostics));
+        Ok((contacts, diagnostics))
+    }
+
+    fn get(&mut self, uid: &str) -> anyhow::Result<Option<Contact>> {
+        Ok(self.contact_list()?.get(uid).cloned())
+    }
+
+    fn insert(&mut self, contact: Contact) -> anyhow::Result<()> {
+        self.contact_list()?.insert(contact)
+    }
+
+    fn update(&mut self, uid: &str, contact: Contact) -> anyhow::Result<()> {
+        self.contact_list()?.update(uid, contact)
+    }
+
+    fn delete(&mut self, uid: &str) -> anyhow::Result<Contact> {
+        self.contact_list()?.delete(uid)
+    }
+
+    /// The contacts are first written to a temporary file which then replaces the contacts file,
+    /// so the store is never left half-written.
+    fn save(&mut self) -> anyhow::Result<()> {
+        let Some(contacts) = &self.contacts else {
+            // Nothing was read, so nothing changed
+            return Ok(());
+        };
+        contacts.check_complete()?;
+
+        let mut contents = Vec::new();
+        json::contacts_to_json_pretty(&mut contents, contacts.contacts.iter())?;
+        if let Some(encryption) = &self.encryption {
+            contents = encryption.encrypt(&contents)?;
+        }
+        write_file(&self.path, &contents)
+            .with_context(|| format!("Failed to save contact store at {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uids_are_only_written_when_saving() {
+        let store = tempfile::tempdir().unwrap();
+        let path = store.path().join(FILE_NAME);
+        let input = r#"[{"name": {"first": "Anna"}}, {"name": {"first": "Anna"}}]"#;
+        fs::write(&path, input).unwrap();
+
+        let load = || {
+            let mut backend = JsonFileBackend::new(store.path(), &Config::default()).unwrap();
+            let (contacts, diagnostics) = backend.load().unwrap();
+            assert!(diagnostics.is_empty());
+            let uids = contacts
+                .iter()
+                .map(|contact| contact.uid().to_owned())
+                .collect::<Vec<_>>();
+            (backend, uids)
+        };
+        let (_, uids) = load();
+        let (mut backend, same_uids) = load();
+        assert_eq!(uids, same_uids);
+        assert_ne!(uids[0], uids[1]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), input);
+
+        backend.save().unwrap();
+        let (_, saved_uids) = load();
+        assert_eq!(saved_uids, uids);
+        assert!(fs::read_to_string(&path).unwrap().contains(&uids[0]));
+    }
+}
@@ -0,0 +1,305 @@
+//! A backend keeping the contacts in an SQLite database
+//!
+//! Every contact is stored as its JSON representation, so the database holds exactly the same data
+//! as `contacts.json` and can be converted with `export --fmt json` and `import --fmt json`. Single
+//! contacts are read and written as needed, so that commands only touching a few contacts do not
+//! have to read all of them. An external-content FTS5 table indexes the names, email addresses,
+//! notes and addresses of the contacts for searching.
+
+use {
+    super::{check_diagnostics, search_fields, Backend, Diagnostic},
+    crate::{json, Contact},
+    anyhow::{bail, Context},
+    country_codes::CountryCode,
+    rusqlite::{params, Connection, OptionalExtension},
+    std::path::PathBuf,
+};
+
+/// The contacts along with the texts that are searched, which are indexed by `contacts_fts`. The
+/// triggers keep the index up to date.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS contacts (
+        id INTEGER PRIMARY KEY,
+        uid TEXT UNIQUE NOT NULL,
+        data TEXT NOT NULL,
+        name TEXT NOT NULL,
+        email TEXT NOT NULL,
+        notes TEXT NOT NULL,
+        address TEXT NOT NULL
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS contacts_fts USING fts5(
+        name,
+        email,
+        notes,
+        address,
+        content = 'contacts',
+        content_rowid = 'id'
+    );
+    CREATE TRIGGER IF NOT EXISTS contacts_insert AFTER INSERT ON contacts BEGIN
+        INSERT INTO contacts_fts (rowid, name, email, notes, address)
+        VALUES (new.id, new.name, new.email, new.notes, new.address);
+    END;
+    CREATE TRIGGER IF NOT EXISTS contacts_delete AFTER DELETE ON contacts BEGIN
+        INSERT INTO contacts_fts (contacts_fts, rowid, name, email, notes, address)
+        VALUES ('delete', old.id, old.name, old.email, old.notes, old.address);
+    END;
+    CREATE TRIGGER IF NOT EXISTS contacts_update AFTER UPDATE ON contacts BEGIN
+        INSERT INTO contacts_fts (contacts_fts, rowid, name, email, notes, address)
+        VALUES ('delete', old.id, old.name, old.email, old.notes, old.address);
+        INSERT INTO contacts_fts (rowid, name, email, notes, address)
+        VALUES (new.id, new.name, new.email, new.notes, new.address);
+    END;
+";
+
+pub struct SqliteBackend {
+    path: PathBuf,
+    connection: Connection,
+    default_region: Option<CountryCode>,
+}
+
+impl SqliteBackend {
+    /// Opens the database at `path`, creating it if it does not exist yet.
+    pub fn open(path: PathBuf, default_region: Option<CountryCode>) -> anyhow::Result<Self> {
+        let connection = Connection::open(&path)
+            .and_then(|connection| connection.execute_batch(SCHEMA).map(|()| connection))
+            .with_context(|| format!("Failed to open contact store at {}", path.display()))?;
+        Ok(SqliteBackend {
+            path,
+            connection,
+            default_region,
+        })
+    }
+
+    /// Starts a transaction for the changes if none is running yet. It is committed when saving,
+    /// so the changes are discarded if the store is not saved.
+    fn begin(&self) -> anyhow::Result<()> {
+        if self.connection.is_autocommit() {
+            self.connection.execute_batch("BEGIN").with_context(|| {
+                format!("Failed to write contact store at {}", self.path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn exists(&self, uid: &str) -> anyhow::Result<bool> {
+        self.connection
+            .query_row("SELECT 1 FROM contacts WHERE uid = ?1", [uid], |_| Ok(()))
+            .optional()
+            .map(|row| row.is_some())
+            .with_context(|| format!("Failed to read contact store at {}", self.path.display()))
+    }
+
+    /// Parses the JSON representation of the contact with the given UID
+    fn parse(&self, uid: &str, data: &str) -> Result<Contact, Vec<Diagnostic>> {
+        json::contact_from_json(data, self.default_region).map_err(|diagnostics| {
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| Diagnostic::Sqlite(self.path.clone(), uid.to_owned(), diagnostic))
+                .collect()
+        })
+    }
+}
+
+impl Backend for SqliteBackend {
+    fn load(&mut self) -> anyhow::Result<(Vec<Contact>, Vec<Diagnostic>)> {
+        let rows = self
+            .connection
+            .prepare("SELECT uid, data FROM contacts ORDER BY id")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .with_context(|| format!("Failed to read contact store at {}", self.path.display()))?;
+
+        let mut contacts = Vec::new();
+        let mut diagnostics = Vec::new();
+        for (uid, data) in rows {
+            match self.parse(&uid, &data) {
+                Ok(contact) => contacts.push(contact),
+                Err(contact_diagnostics) => diagnostics.extend(contact_diagnostics),
+            }
+        }
+
+        Ok((contacts, diagnostics))
+    }
+
+    /// Only the requested contact is read. It is an error if it is invalid.
+    fn get(&mut self, uid: &str) -> anyhow::Result<Option<Contact>> {
+        let data = self
+            .connection
+            .query_row("SELECT data FROM contacts WHERE uid = ?1", [uid], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()
+            .with_context(|| format!("Failed to read contact store at {}", self.path.display()))?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        self.parse(uid, &data)
+            .map(Some)
+            .or_else(|diagnostics| check_diagnostics(diagnostics).map(|()| None))
+    }
+
+    fn insert(&mut self, contact: Contact) -> anyhow::Result<()> {
+        if self.exists(contact.uid())? {
+            bail!(
+                "A contact with the UID \"{}\" already exists",
+                contact.uid()
+            );
+        }
+        let data = json::contact_to_json(&contact)?;
+        let [name, email, notes, address] = search_fields(&contact);
+        self.begin()?;
+        self.connection
+            .execute(
+                "INSERT INTO contacts (uid, data, name, email, notes, address)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![contact.uid(), data, name, email, notes, address],
+            )
+            .with_context(|| format!("Failed to write contact store at {}", self.path.display()))?;
+        Ok(())
+    }
+
+    fn update(&mut self, uid: &str, contact: Contact) -> anyhow::Result<()> {
+        let new_uid = contact.uid();
+        if new_uid != uid && self.exists(new_uid)? {
+            bail!("A contact with the UID \"{new_uid}\" already exists");
+        }
+        let data = json::contact_to_json(&contact)?;
+        let [name, email, notes, address] = search_fields(&contact);
+        self.begin()?;
+        let updated = self
+            .connection
+            .execute(
+                "UPDATE contacts
+                SET uid = ?1, data = ?2, name = ?3, email = ?4, notes = ?5, address = ?6
+                WHERE uid = ?7",
+                params![new_uid, data, name, email, notes, address, uid],
+            )
+            .with_context(|| format!("Failed to write contact store at {}", self.path.display()))?;
+        if updated == 0 {
+            bail!("No contact has the UID \"{uid}\"");
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, uid: &str) -> anyhow::Result<Contact> {
+        let contact = self
+            .get(uid)?
+            .with_context(|| format!("No contact has the UID \"{uid}\""))?;
+        self.begin()?;
+        self.connection
+            .execute("DELETE FROM contacts WHERE uid = ?1", [uid])
+            .with_context(|| format!("Failed to write contact store at {}", self.path.display()))?;
+        Ok(contact)
+    }
+
+    /// All changes are committed in a single transaction.
+    fn save(&mut self) -> anyhow::Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT").with_context(|| {
+                format!("Failed to save contact store at {}", self.path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Uses the full-text index, which matches the words of the query at the start of words. A
+    /// query without words matches no contacts.
+    fn search(&mut self, query: &str) -> anyhow::Result<Option<Vec<String>>> {
+        if query.trim().is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        // Every word is quoted to keep FTS5 from interpreting it as an operator
+        let query = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let uids = self
+            .connection
+            .prepare(
+                "SELECT contacts.uid FROM contacts_fts
+                JOIN contacts ON contacts.id = contacts_fts.rowid
+                WHERE contacts_fts MATCH ?1
+                ORDER BY contacts_fts.rank",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map([query], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .with_context(|| {
+                format!("Failed to search contact store at {}", self.path.display())
+            })?;
+        Ok(Some(uids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(json: &str) -> Contact {
+        json::contact_from_json(json, None).unwrap()
+    }
+
+    #[test]
+    fn contacts_are_read_written_and_searched_by_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.db");
+        let anna = contact(
+            r#"{
+                "uid": "urn:uuid:a",
+                "name": {"first": "Anna", "last": "Muster"},
+                "email": ["anna@example.org"],
+                "notes": "Plays the cello"
+            }"#,
+        );
+        let bob = contact(r#"{"uid": "urn:uuid:b", "name": {"first": "Bob"}}"#);
+
+        let mut backend = SqliteBackend::open(path.clone(), None).unwrap();
+        backend.insert(anna.clone()).unwrap();
+        backend.insert(bob.clone()).unwrap();
+        assert!(backend.insert(bob.clone()).is_err());
+        backend.save().unwrap();
+
+        let mut backend = SqliteBackend::open(path.clone(), None).unwrap();
+        assert_eq!(
+            json::contact_to_json(&backend.get("urn:uuid:a").unwrap().unwrap()).unwrap(),
+            json::contact_to_json(&anna).unwrap()
+        );
+        assert!(backend.get("urn:uuid:c").unwrap().is_none());
+        assert_eq!(
+            backend.search("cel").unwrap(),
+            Some(vec!["urn:uuid:a".to_owned()])
+        );
+        assert_eq!(backend.search("").unwrap(), Some(Vec::new()));
+        assert_eq!(backend.search(" \t ").unwrap(), Some(Vec::new()));
+
+        // The index follows changes right away, but they are only kept when saved
+        let mut changed = bob.clone();
+        changed.notes = Some("Also plays the cello".to_owned());
+        backend.update("urn:uuid:b", changed).unwrap();
+        backend.delete("urn:uuid:a").unwrap();
+        assert_eq!(
+            backend.search("cello").unwrap(),
+            Some(vec!["urn:uuid:b".to_owned()])
+        );
+        drop(backend);
+
+        let mut backend = SqliteBackend::open(path, None).unwrap();
+        let (contacts, diagnostics) = backend.load().unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(
+            backend.search("cello").unwrap(),
+            Some(vec!["urn:uuid:a".to_owned()])
+        );
+    }
+}
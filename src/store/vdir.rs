@@ -0,0 +1,303 @@
+//! A backend keeping the contacts in a vdir, i.e. a directory with one vCard file per contact
+//!
+//! This is the format used by vdirsyncer and khard, so the contacts can be synced with CardDAV
+//! servers. Files are read and written with the importer and exporter of the `vcard` module.
+
+use {
+    super::{check_diagnostics, Backend, ContactList, Diagnostic},
+    crate::{
+        args::{PhotoMode, VcardVersion},
+        vcard::{self, VcardOptions},
+        Contact,
+    },
+    anyhow::{anyhow, Context},
+    country_codes::CountryCode,
+    std::{
+        collections::{HashMap, HashSet},
+        fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+#[derive(Debug)]
+pub struct VdirBackend {
+    path: PathBuf,
+    default_region: Option<CountryCode>,
+    /// The contacts, once they have been read
+    contacts: Option<ContactList>,
+    /// The files the contacts were read from by UID, so that they keep their names when saved
+    file_names: HashMap<String, String>,
+}
+
+impl VdirBackend {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        store_path: P,
+        vdir_path: Q,
+        default_region: Option<CountryCode>,
+    ) -> Self {
+        VdirBackend {
+            path: store_path.as_ref().join(vdir_path),
+            default_region,
+            contacts: None,
+            file_names: HashMap::new(),
+        }
+    }
+
+    /// The contacts, which are read on first use. Invalid vCard files are an error.
+    fn contact_list(&mut self) -> anyhow::Result<&mut ContactList> {
+        if self.contacts.is_none() {
+            let (_, diagnostics) = self.load()?;
+            check_diagnostics(diagnostics)?;
+        }
+        Ok(self.contacts.as_mut().expect("contacts are loaded"))
+    }
+}
+
+impl Backend for VdirBackend {
+    /// Reads all `.vcf` files of the vdir, skipping files that cannot be parsed.
+    ///
+    /// Properties that cannot be converted are kept as they are, so the warnings of the vCard
+    /// importer are not reported. vCards without a UID are given one derived from their file and
+    /// position (see [`Contact::derived_uid`]), which is written back when the store is saved.
+    fn load(&mut self) -> anyhow::Result<(Vec<Contact>, Vec<Diagnostic>)> {
+        let mut file_paths = fs::read_dir(&self.path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .with_context(|| format!("Failed to open contact store at {}", self.path.display()))?;
+        file_paths.retain(|path| path.extension().is_some_and(|extension| extension == "vcf"));
+        file_paths.sort();
+
+        let mut contacts = Vec::new();
+        self.file_names.clear();
+        let mut diagnostics = Vec::new();
+        for file_path in file_paths {
+            let file_name = file_path
+                .file_name()
+                .expect("file in directory")
+                .to_string_lossy()
+                .into_owned();
+            let result = fs::read_to_string(&file_path)
+                .context("Failed to read vCard file")
+                .and_then(|input| vcard::contacts_from_vcard(&input, self.default_region));
+            let file_contacts = match result {
+                Ok((file_contacts, _)) => file_contacts,
+                Err(error) => {
+                    diagnostics.push(Diagnostic::Vcard(file_path, error));
+                    continue;
+                }
+            };
+
+            for (index, mut contact) in file_contacts.into_iter().enumerate() {
+                if contact.uid.is_empty() {
+                    contact.uid = Contact::derived_uid(&format!("{file_name}#{index}"));
+                }
+                let uid = contact.uid().to_owned();
+                if let Some(other_file_name) = self.file_names.get(&uid) {
+                    let error = anyhow!("UID \"{uid}\" is already used in {other_file_name}");
+                    diagnostics.push(Diagnostic::Vcard(file_path.clone(), error));
+                    continue;
+                }
+                self.file_names.insert(uid, file_name.clone());
+                contacts.push(contact);
+            }
+        }
+        self.contacts = Some(ContactList::new(contacts.clone(), &diagnostics));
+        Ok((contacts, diagnostics))
+    }
+
+    fn get(&mut self, uid: &str) -> anyhow::Result<Option<Contact>> {
+        Ok(self.contact_list()?.get(uid).cloned())
+    }
+
+    fn insert(&mut self, contact: Contact) -> anyhow::Result<()> {
+        self.contact_list()?.insert(contact)
+    }
+
+    fn update(&mut self, uid: &str, contact: Contact) -> anyhow::Result<()> {
+        self.contact_list()?.update(uid, contact)
+    }
+
+    fn delete(&mut self, uid: &str) -> anyhow::Result<Contact> {
+        self.contact_list()?.delete(uid)
+    }
+
+    /// Only the files of contacts that changed are written, and only the files of deleted
+    /// contacts are removed, so that tools syncing the vdir see minimal changes.
+    fn save(&mut self) -> anyhow::Result<()> {
+        let Some(contacts) = &self.contacts else {
+            // Nothing was read, so nothing changed
+            return Ok(());
+        };
+
+        // The vCards must be read back into the same contacts, so photos are referenced by their
+        // path in the store rather than embedded
+        let options = VcardOptions {
+            groups: false,
+            photo_mode: PhotoMode::Store,
+            photos_path: PathBuf::new(),
+            derive_time_zone: false,
+            version: VcardVersion::V4_0,
+        };
+
+        let mut file_names = HashMap::new();
+        let mut written = HashSet::new();
+        for contact in &contacts.contacts {
+            let uid = contact.uid().to_owned();
+            // Files containing multiple vCards are split up
+            let file_name = self
+                .file_names
+                .get(&uid)
+                .filter(|file_name| !written.contains(*file_name))
+                .cloned()
+                .unwrap_or_else(|| vcard::vcard_file_name(&uid));
+            let content = vcard::contact_to_vcard(contact, &options)?;
+            vcard::write_vcard_file(&self.path, &file_name, &content).with_context(|| {
+                format!("Failed to save contact store at {}", self.path.display())
+            })?;
+            written.insert(file_name.clone());
+            file_names.insert(uid, file_name);
+        }
+
+        let stale = self
+            .file_names
+            .values()
+            .filter(|file_name| !written.contains(*file_name))
+            .collect::<HashSet<_>>();
+        for file_name in stale {
+            let file_path = self.path.join(file_name);
+            match fs::remove_file(&file_path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                    return Err(error)
+                        .with_context(|| format!("Failed to remove {}", file_path.display()));
+                }
+                _ => (),
+            }
+        }
+
+        self.file_names = file_names;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::json};
+
+    fn to_json(contacts: &[Contact]) -> String {
+        let mut output = Vec::new();
+        json::contacts_to_json_pretty(&mut output, contacts.iter()).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn contacts_survive_a_round_trip_through_the_vdir() {
+        let input = r#"[
+            {
+                "uid": "urn:uuid:2f1a0d3e-7c55-4d0e-9b8a-5b0c2a1e6f10",
+                "name": {
+                    "prefixes": ["Dr."],
+                    "first": "Anna",
+                    "middle": ["Maria"],
+                    "last": "Muster",
+                    "nickname": "Anni",
+                    "sort_as": "Muster Anna"
+                },
+                "bday": "-04-01",
+                "phone": [{"number": "+41791234567", "type": "mobile", "pref": 1}],
+                "email": [{"address": "anna@example.org", "type": "work", "pref": true}],
+                "addresses": [
+                    {
+                        "street": "Bahnhofstrasse",
+                        "number": "1",
+                        "locality": "Zürich",
+                        "postal_code": "8001",
+                        "country": "CH",
+                        "type": "home",
+                        "label": "Zuhause"
+                    }
+                ],
+                "org": "Example AG",
+                "department": "Research",
+                "title": "Scientist",
+                "notes": "First line\nSecond line; with, separators",
+                "relations": [
+                    {"type": "manager", "uid": "urn:uuid:9d7c3b1a-0e2f-4a5b-8c6d-1e2f3a4b5c6d"},
+                    {"type": "assistant", "name": "Bob, the helper"},
+                    {"type": "other", "name": "Carol"}
+                ],
+                "photo": "anna.jpg",
+                "geo": {"latitude": 47.37, "longitude": 8.54},
+                "tz": "Europe/Zurich",
+                "lang": ["de-CH", "en"],
+                "gender": {"sex": "female", "identity": "woman"},
+                "urls": [{"url": "https://example.org/blog", "type": "blog"}],
+                "impp": [
+                    {"service": "matrix", "handle": "@anna:example.org"},
+                    {"service": "signal", "handle": "+41791234567"},
+                    {"service": "xmpp", "handle": "anna@example.org"},
+                    {"service": "telegram", "handle": "@anna"},
+                    {"service": "other", "handle": "skype:anna"}
+                ],
+                "social": [
+                    {"service": "mastodon", "handle": "@anna@mastodon.social"},
+                    {"service": "github", "handle": "anna"},
+                    {"service": "other", "handle": "https://example.org/anna"}
+                ],
+                "tags": ["family", "work"],
+                "extra": {"shoe-size": "38", "favorite-color": "green, sometimes blue"},
+                "vcard_properties": ["X-CUSTOM;X-PARAM=1:kept"]
+            }
+        ]"#;
+        let (contacts, diagnostics) = json::contacts_from_json_lenient(input, None);
+        assert!(diagnostics.is_empty());
+
+        let store = tempfile::tempdir().unwrap();
+        fs::create_dir(store.path().join("contacts")).unwrap();
+        let mut backend = VdirBackend::new(store.path(), "contacts", None);
+        for contact in &contacts {
+            backend.insert(contact.clone()).unwrap();
+        }
+        backend.save().unwrap();
+
+        let vcard = fs::read_to_string(
+            store
+                .path()
+                .join("contacts")
+                .join(vcard::vcard_file_name(contacts[0].uid())),
+        )
+        .unwrap();
+        assert!(vcard.contains("PHOTO:photos/anna.jpg\r\n"), "{vcard}");
+
+        let mut backend = VdirBackend::new(store.path(), "contacts", None);
+        let (loaded, diagnostics) = backend.load().unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(to_json(&loaded), to_json(&contacts));
+    }
+
+    #[test]
+    fn uids_are_only_written_when_saving() {
+        let store = tempfile::tempdir().unwrap();
+        let vdir = store.path().join("contacts");
+        fs::create_dir(&vdir).unwrap();
+        let input = "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Anna\r\nN:;Anna;;;\r\nEND:VCARD\r\n";
+        fs::write(vdir.join("anna.vcf"), input).unwrap();
+
+        let load = || {
+            let mut backend = VdirBackend::new(store.path(), "contacts", None);
+            let (contacts, diagnostics) = backend.load().unwrap();
+            assert!(diagnostics.is_empty());
+            (backend, contacts[0].uid().to_owned())
+        };
+        let (_, uid) = load();
+        let (mut backend, same_uid) = load();
+        assert_eq!(uid, same_uid);
+        assert_eq!(fs::read_to_string(vdir.join("anna.vcf")).unwrap(), input);
+
+        backend.save().unwrap();
+        let vcard = fs::read_to_string(vdir.join("anna.vcf")).unwrap();
+        assert!(vcard.contains(&format!("UID:{uid}\r\n")), "{vcard}");
+    }
+}
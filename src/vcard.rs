@@ -1,32 +1,382 @@
 use {
-    crate::{Contact, PhoneNumberType},
-    anyhow::Context,
+    crate::{
+        args::{PhoneNumberFormat, PhotoMode, VcardVersion},
+        photo::{validate_photo_file_name, Photo},
+        validate_extra_key, validate_language_tag, validate_pref, validate_tag, validate_uri,
+        Address, AddressType, Contact, EmailAddress, EmailAddressType, Gender, Geo, ImppHandle,
+        Organization, PartialDate, PhoneNumber, PhoneNumberType, Relation, RelationTarget,
+        RelationType, Sex, SocialProfile, SocialService, Website, WebsiteType,
+    },
+    anyhow::{anyhow, bail, Context},
+    base64::prelude::{Engine, BASE64_STANDARD},
+    chrono::{TimeZone, Utc},
+    chrono_tz::{OffsetComponents, Tz},
+    country_codes::CountryCode,
     ical_vcard::{Contentline, Identifier, Param, ParamValue, Value},
-    std::{io::Write, iter::IntoIterator},
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap, HashSet},
+        fs,
+        io::{self, Write},
+        iter::IntoIterator,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+    uuid::Uuid,
 };
 
-pub fn contacts_to_vcard<'a, C, W>(writer: W, contacts: C) -> anyhow::Result<()>
+#[derive(Clone, Debug)]
+pub struct VcardOptions {
+    /// Whether to add a vCard of kind "group" for every tag, referencing the contacts having that
+    /// tag as its members
+    pub groups: bool,
+    pub photo_mode: PhotoMode,
+    /// The directory containing the photos of the contacts
+    pub photos_path: PathBuf,
+    /// Whether to derive missing time zones from the countries of the contacts
+    pub derive_time_zone: bool,
+    pub version: VcardVersion,
+}
+
+pub fn contacts_to_vcard<'a, C, W>(
+    writer: W,
+    contacts: C,
+    options: &VcardOptions,
+) -> anyhow::Result<()>
 where
     C: IntoIterator<Item = &'a Contact>,
     W: Write,
 {
-    let mut writer = ical_vcard::Writer::new(writer);
-    for contact in contacts {
-        writer
-            .write_all(
-                contact_to_contentlines(contact)
-                    .context("Contact could not be serialized to vCard")?,
-            )
+    let mut writer = writer;
+    for (_, contentlines) in vcards(contacts, options)? {
+        write_contentlines(&mut writer, contentlines, options.version)
+            .context("Failed to write contacts to vCard")?;
+    }
+
+    Ok(())
+}
+
+/// The number of files changed by [`contacts_to_vcard_dir`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DirSummary {
+    pub written: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// The prefix of photo references relative to the store (see [`PhotoMode::Store`])
+const STORE_PHOTO_PREFIX: &str = "photos/";
+
+/// The file listing the files written by [`contacts_to_vcard_dir`] into a directory
+const MANIFEST_FILE_NAME: &str = ".tactful-export";
+
+/// Writes every contact into a separate `.vcf` file in the directory at `path`, as expected by
+/// tools like vdirsyncer and khard.
+///
+/// Files are named after the UIDs of the contacts. Only files whose content changed are written.
+/// The written files are listed in a manifest in the directory, and if `remove_stale` is set, the
+/// files listed by a previous export that were not written again are removed. Files that were not
+/// written by this function are never touched.
+pub fn contacts_to_vcard_dir<'a, C, P>(
+    path: P,
+    contacts: C,
+    options: &VcardOptions,
+    remove_stale: bool,
+) -> anyhow::Result<DirSummary>
+where
+    C: IntoIterator<Item = &'a Contact>,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    fs::create_dir_all(path)
+        .with_context(|| format!("Failed to create directory {}", path.display()))?;
+
+    let mut summary = DirSummary::default();
+    let mut file_names = HashSet::new();
+    for (uid, contentlines) in vcards(contacts, options)? {
+        let file_name = vcard_file_name(&uid);
+        if !file_names.insert(file_name.clone()) {
+            bail!("Multiple contacts have the UID \"{uid}\"");
+        }
+
+        let mut content = Vec::new();
+        write_contentlines(&mut content, contentlines, options.version)
             .context("Failed to write contacts to vCard")?;
+
+        if write_vcard_file(path, &file_name, &content)? {
+            summary.written += 1;
+        } else {
+            summary.unchanged += 1;
+        }
+    }
+
+    let manifest_path = path.join(MANIFEST_FILE_NAME);
+    let previous_file_names = match fs::read_to_string(&manifest_path) {
+        Ok(manifest) => manifest.lines().map(str::to_owned).collect::<Vec<_>>(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("Failed to read {}", manifest_path.display()))
+        }
+    };
+    for file_name in previous_file_names {
+        if file_names.contains(&file_name) {
+            continue;
+        }
+        // Manifests are not trusted to only contain file names
+        if !remove_stale || !file_name.ends_with(".vcf") || file_name.contains(['/', '\\']) {
+            file_names.insert(file_name);
+            continue;
+        }
+        let file_path = path.join(&file_name);
+        match fs::remove_file(&file_path) {
+            Ok(()) => summary.removed += 1,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Failed to remove {}", file_path.display()))
+            }
+        }
+    }
+
+    let mut manifest = file_names.into_iter().collect::<Vec<_>>();
+    manifest.sort();
+    fs::write(&manifest_path, manifest.join("\n") + "\n")
+        .with_context(|| format!("Failed to write {}", manifest_path.display()))?;
+
+    Ok(summary)
+}
+
+/// Serializes a single contact to a vCard.
+pub fn contact_to_vcard(contact: &Contact, options: &VcardOptions) -> anyhow::Result<Vec<u8>> {
+    let contentlines = contact_to_contentlines(contact, options)
+        .with_context(|| format!("Contact {} could not be serialized to vCard", contact.name))?;
+    let mut content = Vec::new();
+    write_contentlines(&mut content, contentlines, options.version)
+        .context("Failed to write contact to vCard")?;
+    Ok(content)
+}
+
+/// Writes the content lines of a vCard.
+///
+/// vCard 2.1 is not written by `ical_vcard`, as quoted-printable values must not be folded but
+/// wrapped with soft line breaks (see RFC 2045, section 6.7). Other lines are folded before
+/// whitespace, which is part of the value when unfolding vCard 2.1 (see [`unfold`]).
+fn write_contentlines<W: Write>(
+    mut writer: W,
+    contentlines: Vec<Contentline>,
+    version: VcardVersion,
+) -> io::Result<()> {
+    if version != VcardVersion::V2_1 {
+        return ical_vcard::Writer::new(writer).write_all(contentlines);
+    }
+
+    for contentline in &contentlines {
+        let quoted_printable = param_values(contentline, "ENCODING")
+            .iter()
+            .any(|encoding| encoding.eq_ignore_ascii_case("QUOTED-PRINTABLE"));
+        let line = format_contentline(contentline);
+        let mut output = String::with_capacity(line.len() + 2);
+        if quoted_printable {
+            let value_start = line.len() - contentline.value.value().len();
+            let (start, value) = line.split_at(value_start);
+            output.push_str(start);
+            let mut length = start.len();
+            let mut rest = value;
+            while !rest.is_empty() {
+                // Escape sequences like "=0A" must stay on one line
+                let token = if rest.starts_with('=') { 3 } else { 1 }.min(rest.len());
+                if length + token > 75 {
+                    output.push_str("=\r\n");
+                    length = 0;
+                }
+                output.push_str(&rest[..token]);
+                length += token;
+                rest = &rest[token..];
+            }
+        } else {
+            // Lines without whitespace cannot be folded and are left long
+            let mut length = 0;
+            let mut rest = line.as_str();
+            while !rest.is_empty() {
+                let word_end = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|(_, c)| matches!(c, ' ' | '\t'))
+                    .map_or(rest.len(), |(index, _)| index);
+                if length > 0 && length + word_end > 75 {
+                    output.push_str("\r\n");
+                    length = 0;
+                }
+                output.push_str(&rest[..word_end]);
+                length += word_end;
+                rest = &rest[word_end..];
+            }
+        }
+        output.push_str("\r\n");
+        writer.write_all(output.as_bytes())?;
     }
     Ok(())
 }
 
+/// Writes a file in the directory at `path`, unless it already has the given content.
+///
+/// The content is first written to a hidden temporary file which then replaces the file, so that
+/// tools watching the directory never see a half-written file. Returns whether the file was
+/// written.
+pub fn write_vcard_file(path: &Path, file_name: &str, content: &[u8]) -> anyhow::Result<bool> {
+    let file_path = path.join(file_name);
+    if fs::read(&file_path).is_ok_and(|existing| existing == content) {
+        return Ok(false);
+    }
+    let temp_path = path.join(format!(".{file_name}.tmp"));
+    fs::write(&temp_path, content)
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+    fs::rename(&temp_path, &file_path)
+        .with_context(|| format!("Failed to write {}", file_path.display()))?;
+    Ok(true)
+}
+
+/// Converts the contacts, followed by the groups if enabled, to vCards along with their UIDs.
+fn vcards<'a, C>(
+    contacts: C,
+    options: &VcardOptions,
+) -> anyhow::Result<Vec<(String, Vec<Contentline<'static>>)>>
+where
+    C: IntoIterator<Item = &'a Contact>,
+{
+    let mut vcards = Vec::new();
+    let mut members = BTreeMap::<&str, Vec<String>>::new();
+    for contact in contacts {
+        let contentlines = contact_to_contentlines(contact, options).with_context(|| {
+            format!("Contact {} could not be serialized to vCard", contact.name)
+        })?;
+        vcards.push((contact.uid().to_owned(), contentlines));
+        for tag in &contact.tags {
+            members
+                .entry(tag)
+                .or_default()
+                .push(contact.uid().to_owned());
+        }
+    }
+
+    if options.groups {
+        for (tag, members) in members {
+            vcards.push((
+                group_uid(tag),
+                group_to_contentlines(tag, &members, options.version)
+                    .context("Group could not be serialized to vCard")?,
+            ));
+        }
+    }
+
+    Ok(vcards)
+}
+
+/// The name of the file containing the vCard with the given UID.
+///
+/// UUID URNs are shortened to the UUID. Other UIDs that cannot be used as file names safely are
+/// replaced by a UUID derived from them.
+pub fn vcard_file_name(uid: &str) -> String {
+    let name = uid.strip_prefix("urn:uuid:").unwrap_or(uid);
+    let is_safe = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'));
+    if is_safe {
+        format!("{name}.vcf")
+    } else {
+        format!("{}.vcf", Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.as_bytes()))
+    }
+}
+
+fn group_uid(tag: &str) -> String {
+    let uid = Uuid::new_v5(
+        &Uuid::NAMESPACE_URL,
+        format!("tactful:tag:{tag}").as_bytes(),
+    );
+    format!("urn:uuid:{uid}")
+}
+
+/// Creates a vCard of kind "group" representing a tag.
+///
+/// vCard 3.0 and 2.1 have no groups, so Apple's extension is used for them.
+fn group_to_contentlines(
+    tag: &str,
+    members: &[String],
+    version: VcardVersion,
+) -> anyhow::Result<Vec<Contentline<'static>>> {
+    let (kind, member_property) = match version {
+        VcardVersion::V4_0 => ("KIND", "MEMBER"),
+        VcardVersion::V3_0 | VcardVersion::V2_1 => {
+            ("X-ADDRESSBOOKSERVER-KIND", "X-ADDRESSBOOKSERVER-MEMBER")
+        }
+    };
+
+    let mut contentlines = vec![
+        Contentline {
+            group: None,
+            name: Identifier::new("BEGIN").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new("VCARD").expect("valid value"),
+        },
+        Contentline {
+            group: None,
+            name: Identifier::new("VERSION").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(version.name()).expect("valid value"),
+        },
+        Contentline {
+            group: None,
+            name: Identifier::new(kind).expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new("group").expect("valid value"),
+        },
+        Contentline {
+            group: None,
+            name: Identifier::new("FN").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(escape_component(tag))
+                .context("Failed to write tag to contentline")?,
+        },
+        Contentline {
+            group: None,
+            name: Identifier::new("UID").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(group_uid(tag)).expect("valid value"),
+        },
+    ];
+    for member in members {
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new(member_property).expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(member.to_owned())
+                .context("Failed to write member to contentline")?,
+        });
+    }
+    contentlines.push(Contentline {
+        group: None,
+        name: Identifier::new("END").expect("valid identifier"),
+        params: Vec::new(),
+        value: Value::new("VCARD").expect("valid value"),
+    });
+
+    match version {
+        VcardVersion::V2_1 => contentlines.into_iter().map(to_vcard_2_1).collect(),
+        VcardVersion::V3_0 | VcardVersion::V4_0 => Ok(contentlines),
+    }
+}
+
 // TODO this function is very ugly, make it cleaner. The problem is mostly due to the `ical_vcard`
 // crate not being very ergonomic. All its functions return results which adds a lot of clutter and
 // thus makes this function very unreadable. Solution: Add panicking variants of the functions to
 // `ical_vcard`.
-fn contact_to_contentlines(contact: &Contact) -> anyhow::Result<Vec<Contentline<'static>>> {
+fn contact_to_contentlines(
+    contact: &Contact,
+    options: &VcardOptions,
+) -> anyhow::Result<Vec<Contentline<'static>>> {
     let mut contentlines = vec![
         Contentline {
             group: None,
@@ -38,97 +388,489 @@ fn contact_to_contentlines(contact: &Contact) -> anyhow::Result<Vec<Contentline<
             group: None,
             name: Identifier::new("VERSION").expect("valid identifier"),
             params: Vec::new(),
-            value: Value::new("4.0").expect("valid value"),
+            value: Value::new(options.version.name()).expect("valid value"),
         },
         Contentline {
             group: None,
             name: Identifier::new("FN").expect("valid identifier"),
             params: Vec::new(),
-            value: Value::new(format!("{} {}", &contact.name.first, &contact.name.last)).context(
+            value: Value::new(contact.name.to_string()).context(
                 "Failed to write name to contentline because it contains control characters",
             )?,
         },
-        Contentline {
+    ];
+
+    let name = &contact.name;
+    let mut params = Vec::new();
+    if let (Some(sort_as), VcardVersion::V4_0) = (&name.sort_as, options.version) {
+        params.push(
+            Param::new(
+                Identifier::new("SORT-AS").expect("valid identifier"),
+                vec![ParamValue::new(sort_as.clone())
+                    .context("Failed to write sort name to contentline")?],
+            )
+            .expect("valid parameter"),
+        );
+    }
+    contentlines.push(Contentline {
+        group: None,
+        name: Identifier::new("N").expect("valid identifier"),
+        params,
+        value: Value::new(
+            [
+                escape_component(&name.last),
+                escape_component(&name.first),
+                escape_list(&name.middle),
+                escape_list(&name.prefixes),
+                escape_list(&name.suffixes),
+            ]
+            .join(";"),
+        )
+        .context("Failed to write name to contentline because it contains control characters")?,
+    });
+    if let (Some(sort_as), VcardVersion::V3_0) = (&name.sort_as, options.version) {
+        contentlines.push(Contentline {
             group: None,
-            name: Identifier::new("N").expect("valid identifier"),
+            name: Identifier::new("SORT-STRING").expect("valid identifier"),
             params: Vec::new(),
-            value: Value::new(format!("{};{};;;", &contact.name.last, &contact.name.first))
-                .context(
-                    "Failed to write name to contentline because it contains control characters",
-                )?,
-        },
-    ];
+            value: Value::new(escape_component(sort_as))
+                .context("Failed to write sort name to contentline")?,
+        });
+    }
+    if let Some(nickname) = &name.nickname {
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("NICKNAME").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(escape_component(nickname))
+                .context("Failed to write nickname to contentline")?,
+        });
+    }
 
-    for phone_number in &contact.phone_numbers {
-        let param_value = match phone_number.ty {
-            PhoneNumberType::Mobile => "cell",
-            PhoneNumberType::Home => "home",
-            PhoneNumberType::Work => "work",
+    let mut label_groups = LabelGroups::new(contact);
+
+    let mut phone_numbers = contact.phone_numbers.iter().collect::<Vec<_>>();
+    phone_numbers.sort_by_key(|phone_number| phone_number.pref.unwrap_or(u8::MAX));
+    for (index, phone_number) in phone_numbers.into_iter().enumerate() {
+        let formatted_number = phone_number.format(PhoneNumberFormat::E164);
+
+        // vCard 4.0 uses tel URIs, older versions plain text
+        let (mut params, value) = match options.version {
+            VcardVersion::V4_0 => (
+                vec![param("VALUE", ["uri"])],
+                format!("tel:{formatted_number}"),
+            ),
+            VcardVersion::V3_0 | VcardVersion::V2_1 => (Vec::new(), formatted_number),
         };
-        let formatted_number = phone_number
-            .number
-            .chars()
-            .filter(|c| !c.is_whitespace())
-            .collect::<String>();
+        if !phone_number.types.is_empty() {
+            params.push(param(
+                "TYPE",
+                phone_number
+                    .types
+                    .iter()
+                    .map(|&ty| phone_number_type_to_vcard(ty, options.version)),
+            ));
+        }
+        params.extend(
+            phone_number
+                .pref
+                .and_then(|pref| pref_param(pref, index == 0, options.version)),
+        );
+
+        push_with_label(
+            &mut contentlines,
+            Contentline {
+                group: None,
+                name: Identifier::new("TEL").expect("valid identifier"),
+                params,
+                value: Value::new(value).expect("valid value"),
+            },
+            phone_number.label.as_deref(),
+            &mut label_groups,
+        )
+        .context("Failed to write phone number label to contentline")?;
+    }
+
+    let mut email_addresses = contact.email_addresses.iter().collect::<Vec<_>>();
+    email_addresses.sort_by_key(|email_address| !email_address.pref);
+    for email_address in email_addresses {
+        let mut params = Vec::new();
+        if options.version != VcardVersion::V4_0 {
+            params.push(param("TYPE", ["internet"]));
+        }
+        match email_address.ty {
+            EmailAddressType::Home => params.push(param("TYPE", ["home"])),
+            EmailAddressType::Work => params.push(param("TYPE", ["work"])),
+            EmailAddressType::Other => (),
+        }
+        if email_address.pref {
+            params.extend(pref_param(1, true, options.version));
+        }
 
         contentlines.push(Contentline {
             group: None,
-            name: Identifier::new("TEL").expect("valid identifier"),
-            params: vec![
-                Param::new(
-                    Identifier::new("VALUE").expect("valid identifier"),
-                    vec![ParamValue::new("uri").expect("valid parameter value")],
+            name: Identifier::new("EMAIL").expect("valid identifier"),
+            params,
+            value: Value::new(email_address.address.to_owned())
+                .context("Failed to write email address to contentline")?,
+        });
+    }
+
+    let mut addresses = contact.addresses.iter().collect::<Vec<_>>();
+    addresses.sort_by_key(|address| address.pref.unwrap_or(u8::MAX));
+    for (index, address) in addresses.into_iter().enumerate() {
+        let mut params = Vec::new();
+        match address.ty {
+            AddressType::Home => params.push(param("TYPE", ["home"])),
+            AddressType::Work => params.push(param("TYPE", ["work"])),
+            AddressType::Other => (),
+        }
+        params.extend(
+            address
+                .pref
+                .and_then(|pref| pref_param(pref, index == 0, options.version)),
+        );
+        // The formatted address is a parameter in vCard 4.0, but a separate property before
+        let postal_lines = address.postal_lines().join("\n");
+        let label_property = match options.version {
+            VcardVersion::V4_0 => {
+                params.push(
+                    Param::new(
+                        Identifier::new("LABEL").expect("valid identifier"),
+                        vec![ParamValue::new(postal_lines)
+                            .context("Failed to write address label to contentline")?],
+                    )
+                    .expect("valid parameter"),
+                );
+                None
+            }
+            VcardVersion::V3_0 | VcardVersion::V2_1 => Some(Contentline {
+                group: None,
+                name: Identifier::new("LABEL").expect("valid identifier"),
+                params: params.clone(),
+                value: Value::new(escape_component(&postal_lines))
+                    .context("Failed to write address label to contentline")?,
+            }),
+        };
+
+        push_with_label(
+            &mut contentlines,
+            Contentline {
+                group: None,
+                name: Identifier::new("ADR").expect("valid identifier"),
+                params,
+                value: Value::new(
+                    [
+                        address.po_box.as_deref().unwrap_or_default(),
+                        address.extended.as_deref().unwrap_or_default(),
+                        &address.street_line(),
+                        &address.locality,
+                        address.region.as_deref().unwrap_or_default(),
+                        &address.postal_code,
+                        address.country.name,
+                    ]
+                    .map(escape_component)
+                    .join(";"),
                 )
-                .expect("valid parameter"),
+                .context("Failed to write address to contentline")?,
+            },
+            address.label.as_deref(),
+            &mut label_groups,
+        )
+        .context("Failed to write address label to contentline")?;
+        contentlines.extend(label_property);
+    }
+
+    if let Some(organization) = &contact.organization {
+        let mut components = vec![escape_component(&organization.name)];
+        components.extend(organization.department.as_deref().map(escape_component));
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("ORG").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(components.join(";"))
+                .context("Failed to write organization to contentline")?,
+        });
+    }
+    for (name, value) in [("TITLE", &contact.title), ("ROLE", &contact.role)] {
+        if let Some(value) = value {
+            contentlines.push(Contentline {
+                group: None,
+                name: Identifier::new(name).expect("valid identifier"),
+                params: Vec::new(),
+                value: Value::new(escape_component(value))
+                    .with_context(|| format!("Failed to write {name} to contentline"))?,
+            });
+        }
+    }
+
+    // vCard 3.0 and 2.1 have no equivalent of relations, languages and gender
+    let (relations, languages, gender) = match options.version {
+        VcardVersion::V4_0 => (
+            &contact.relations[..],
+            &contact.languages[..],
+            contact.gender.as_ref(),
+        ),
+        VcardVersion::V3_0 | VcardVersion::V2_1 => (&[][..], &[][..], None),
+    };
+    for relation in relations {
+        let mut params = Vec::new();
+        let value = match &relation.target {
+            RelationTarget::Contact(uid) => uid.to_owned(),
+            RelationTarget::Person(name) => {
+                params.push(param("VALUE", ["text"]));
+                escape_component(name)
+            }
+        };
+        if let Some(ty) = relation_type_to_vcard(relation.ty) {
+            params.push(param("TYPE", [ty]));
+        }
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("RELATED").expect("valid identifier"),
+            params,
+            value: Value::new(value).context("Failed to write relation to contentline")?,
+        });
+    }
+    for (index, language) in languages.iter().enumerate() {
+        // Preferences range from 1 to 100, so the least preferred languages share the last one
+        let pref = u8::try_from(index + 1).unwrap_or(u8::MAX).min(100);
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("LANG").expect("valid identifier"),
+            params: Vec::from_iter(pref_param(pref, index == 0, options.version)),
+            value: Value::new(language.to_owned()).expect("valid value"),
+        });
+    }
+    if let Some(gender) = gender {
+        let sex = match gender.sex {
+            Some(Sex::Male) => "M",
+            Some(Sex::Female) => "F",
+            Some(Sex::Other) => "O",
+            Some(Sex::NotApplicable) => "N",
+            Some(Sex::Unknown) => "U",
+            None => "",
+        };
+        let value = match &gender.identity {
+            Some(identity) => format!("{sex};{}", escape_component(identity)),
+            None => sex.to_owned(),
+        };
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("GENDER").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(value).context("Failed to write gender to contentline")?,
+        });
+    }
+    if let Some(geo) = &contact.geo {
+        let value = match options.version {
+            VcardVersion::V4_0 => format!("geo:{},{}", geo.latitude, geo.longitude),
+            VcardVersion::V3_0 => format!("{};{}", geo.latitude, geo.longitude),
+            VcardVersion::V2_1 => format!("{},{}", geo.latitude, geo.longitude),
+        };
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("GEO").expect("valid identifier"),
+            params: Vec::new(),
+            value: Value::new(value).expect("valid value"),
+        });
+    }
+    if let Some(time_zone) = contact.local_time_zone(options.derive_time_zone) {
+        // vCard 2.1 only knows UTC offsets, the standard offset (without daylight saving time) is
+        // used for it
+        let (params, value) = match options.version {
+            VcardVersion::V4_0 => (Vec::new(), time_zone.name().to_owned()),
+            VcardVersion::V3_0 => (vec![param("VALUE", ["text"])], time_zone.name().to_owned()),
+            VcardVersion::V2_1 => (Vec::new(), standard_utc_offset(time_zone)),
+        };
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("TZ").expect("valid identifier"),
+            params,
+            value: Value::new(value).expect("valid value"),
+        });
+    }
+    for website in &contact.websites {
+        let params = match website.ty {
+            WebsiteType::Home => vec![param("TYPE", ["home"])],
+            WebsiteType::Work => vec![param("TYPE", ["work"])],
+            WebsiteType::Blog => vec![param("TYPE", ["x-blog"])],
+            WebsiteType::Other => Vec::new(),
+        };
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("URL").expect("valid identifier"),
+            params,
+            value: Value::new(website.url.to_owned())
+                .context("Failed to write URL to contentline")?,
+        });
+    }
+    for impp_handle in &contact.impp {
+        // `X-SERVICE-TYPE` is not part of the vCard standard, but it is understood by most clients
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("IMPP").expect("valid identifier"),
+            params: vec![param("X-SERVICE-TYPE", [impp_handle.service.name()])],
+            value: Value::new(impp_handle.uri())
+                .context("Failed to write instant messaging handle to contentline")?,
+        });
+    }
+    for social_profile in &contact.social_profiles {
+        // Apple's format, which is understood by most clients
+        let mut params = vec![param(
+            "TYPE",
+            [social_profile.service.name().to_lowercase()],
+        )];
+        if social_profile.service != SocialService::Other {
+            params.push(
                 Param::new(
-                    Identifier::new("TYPE").expect("valid identifier"),
-                    vec![ParamValue::new(param_value).expect("valid parameter value")],
+                    Identifier::new("X-USER").expect("valid identifier"),
+                    vec![ParamValue::new(social_profile.user().to_owned())
+                        .context("Failed to write social profile to contentline")?],
                 )
                 .expect("valid parameter"),
-            ],
-            value: Value::new(format!("tel:{formatted_number}")).expect("valid value"),
+            );
+        }
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("X-SOCIALPROFILE").expect("valid identifier"),
+            params,
+            value: Value::new(social_profile.url())
+                .context("Failed to write social profile to contentline")?,
         });
     }
-
-    for email_address in &contact.email_addresses {
+    // A broken photo should not keep the contact from being exported
+    let photo = match options.photo_mode {
+        PhotoMode::Store => None,
+        PhotoMode::Embed | PhotoMode::Uri => contact.photo.as_ref().and_then(|file_name| {
+            Photo::load(&options.photos_path, file_name)
+                .inspect_err(|error| {
+                    eprintln!(
+                        "warning: Leaving out the photo of {}: {error:#}",
+                        contact.name
+                    )
+                })
+                .ok()
+        }),
+    };
+    if let (PhotoMode::Store, Some(file_name)) = (options.photo_mode, &contact.photo) {
         contentlines.push(Contentline {
             group: None,
-            name: Identifier::new("EMAIL").expect("valid identifier"),
+            name: Identifier::new("PHOTO").expect("valid identifier"),
             params: Vec::new(),
-            value: Value::new(email_address.to_owned())
-                .context("Failed to write email address to contentline")?,
+            value: Value::new(format!("{STORE_PHOTO_PREFIX}{file_name}"))
+                .context("Failed to write photo to contentline")?,
         });
     }
-
-    if let Some(address) = &contact.address {
+    if let Some(photo) = photo {
+        let media_subtype = photo.image_type.media_type()["image/".len()..].to_ascii_uppercase();
+        let (params, value) = match (options.photo_mode, options.version) {
+            (PhotoMode::Embed, VcardVersion::V4_0) => (
+                Vec::new(),
+                format!(
+                    "data:{};base64,{}",
+                    photo.image_type.media_type(),
+                    BASE64_STANDARD.encode(&photo.data)
+                ),
+            ),
+            (PhotoMode::Embed, VcardVersion::V3_0) => (
+                vec![param("ENCODING", ["b"]), param("TYPE", [media_subtype])],
+                BASE64_STANDARD.encode(&photo.data),
+            ),
+            (PhotoMode::Embed, VcardVersion::V2_1) => (
+                vec![
+                    param("ENCODING", ["BASE64"]),
+                    param("TYPE", [media_subtype]),
+                ],
+                BASE64_STANDARD.encode(&photo.data),
+            ),
+            (PhotoMode::Uri, VcardVersion::V4_0) => (
+                vec![param("MEDIATYPE", [photo.image_type.media_type()])],
+                photo.file_uri(),
+            ),
+            (PhotoMode::Uri, VcardVersion::V3_0) => (
+                vec![param("VALUE", ["uri"]), param("TYPE", [media_subtype])],
+                photo.file_uri(),
+            ),
+            (PhotoMode::Uri, VcardVersion::V2_1) => (
+                vec![param("VALUE", ["URL"]), param("TYPE", [media_subtype])],
+                photo.file_uri(),
+            ),
+            (PhotoMode::Store, _) => unreachable!("photos are not loaded in store mode"),
+        };
         contentlines.push(Contentline {
             group: None,
-            name: Identifier::new("ADR").expect("valid identifier"),
+            name: Identifier::new("PHOTO").expect("valid identifier"),
+            params,
+            value: Value::new(value).expect("valid value"),
+        });
+    }
+    if !contact.tags.is_empty() {
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new("CATEGORIES").expect("valid identifier"),
             params: Vec::new(),
-            value: Value::new(format!(
-                ";;{street} {number};{locality};;{postal_code};{country}",
-                street = address.street,
-                number = address.number,
-                locality = address.locality,
-                postal_code = address.postal_code,
-                country = address.country.name
-            ))
-            .context("Failed to write address to contentline")?,
+            value: Value::new(escape_list(&contact.tags))
+                .context("Failed to write tags to contentline")?,
         });
     }
-
-    if let Some(birthday) = &contact.birthday {
+    if let Some(notes) = &contact.notes {
         contentlines.push(Contentline {
             group: None,
-            name: Identifier::new("BDAY").expect("valid identifier"),
+            name: Identifier::new("NOTE").expect("valid identifier"),
             params: Vec::new(),
-            value: Value::new(
+            value: Value::new(escape_component(notes))
+                .context("Failed to write notes to contentline")?,
+        });
+    }
+    for (key, value) in &contact.extra {
+        contentlines.push(Contentline {
+            group: None,
+            name: Identifier::new(format!("X-{}", key.to_ascii_uppercase()))
+                .with_context(|| format!("Invalid custom field name \"{key}\""))?,
+            params: Vec::new(),
+            value: Value::new(escape_component(value)).with_context(|| {
+                format!("Failed to write custom field \"{key}\" to contentline")
+            })?,
+        });
+    }
+
+    if let Some(birthday) = &contact.birthday {
+        let birthday = match options.version {
+            VcardVersion::V4_0 => Some((
+                Vec::new(),
                 birthday
                     .to_vcard_string_repr()
                     .context("Failed to write birthday to contentline")?,
-            )
-            .expect("valid value"),
-        });
+            )),
+            VcardVersion::V3_0 | VcardVersion::V2_1 => legacy_birthday(birthday),
+        };
+        if let Some((params, value)) = birthday {
+            contentlines.push(Contentline {
+                group: None,
+                name: Identifier::new("BDAY").expect("valid identifier"),
+                params,
+                value: Value::new(value).expect("valid value"),
+            });
+        }
+    }
+
+    contentlines.push(Contentline {
+        group: None,
+        name: Identifier::new("UID").expect("valid identifier"),
+        params: Vec::new(),
+        value: Value::new(contact.uid().to_owned())
+            .context("Failed to write UID to contentline")?,
+    });
+
+    // Properties of imported vCards that tactful does not understand
+    contentlines.extend(verbatim_properties(contact, options.version));
+
+    if options.version == VcardVersion::V2_1 {
+        contentlines = contentlines
+            .into_iter()
+            .map(to_vcard_2_1)
+            .collect::<anyhow::Result<_>>()?;
     }
 
     contentlines.push(Contentline {
@@ -140,3 +882,1633 @@ fn contact_to_contentlines(contact: &Contact) -> anyhow::Result<Vec<Contentline<
 
     Ok(contentlines)
 }
+
+/// The verbatim properties of a contact that can be written in the given vCard version
+///
+/// Properties that only exist in vCard 4.0 are left out for older versions, along with the labels
+/// attached to them.
+fn verbatim_properties(contact: &Contact, version: VcardVersion) -> Vec<Contentline<'static>> {
+    if version == VcardVersion::V4_0 {
+        return contact.vcard_properties.clone();
+    }
+
+    let is_vcard_4_0_only = |contentline: &Contentline| {
+        let name = contentline.name.value().to_ascii_uppercase();
+        let value = contentline.value.value();
+        matches!(
+            name.as_str(),
+            "ANNIVERSARY"
+                | "CLIENTPIDMAP"
+                | "GENDER"
+                | "KIND"
+                | "LANG"
+                | "MEMBER"
+                | "RELATED"
+                | "XML"
+        ) || value
+            .get(..5)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+            || (name == "TEL"
+                && (value
+                    .get(..4)
+                    .is_some_and(|scheme| scheme.eq_ignore_ascii_case("tel:"))
+                    || param_values(contentline, "VALUE")
+                        .iter()
+                        .any(|value| value.eq_ignore_ascii_case("uri"))))
+    };
+    let left_out_groups = contact
+        .vcard_properties
+        .iter()
+        .filter(|contentline| is_vcard_4_0_only(contentline))
+        .filter_map(|contentline| Some(contentline.group.as_ref()?.value().to_ascii_lowercase()))
+        .collect::<HashSet<_>>();
+    contact
+        .vcard_properties
+        .iter()
+        .filter(|contentline| {
+            !is_vcard_4_0_only(contentline)
+                && contentline.group.as_ref().is_none_or(|group| {
+                    !left_out_groups.contains(&group.value().to_ascii_lowercase())
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Escapes a component of a structured property value such as `ADR` (see RFC 6350, section 3.4).
+fn escape_component(component: &str) -> String {
+    let mut escaped = String::with_capacity(component.len());
+    for c in component.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes a list of values making up one component of a structured property value.
+fn escape_list(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| escape_component(value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Creates a parameter whose name and values are known to be valid.
+fn param<V, I>(name: &'static str, values: I) -> Param<'static>
+where
+    V: Into<Cow<'static, str>>,
+    I: IntoIterator<Item = V>,
+{
+    Param::new(
+        Identifier::new(name).expect("valid identifier"),
+        values
+            .into_iter()
+            .map(|value| ParamValue::new(value).expect("valid parameter value"))
+            .collect(),
+    )
+    .expect("valid parameter")
+}
+
+/// Creates a parameter from a name and values that may be invalid, e.g. because they were imported.
+fn param_from_strings(name: String, values: Vec<String>) -> anyhow::Result<Param<'static>> {
+    let values = values
+        .into_iter()
+        .map(ParamValue::new)
+        .collect::<Result<_, _>>()?;
+    Ok(Param::new(Identifier::new(name)?, values)?)
+}
+
+/// Creates the parameter marking the preference of a property.
+///
+/// vCard 3.0 and 2.1 cannot rank properties, they only mark the preferred one, which is the first
+/// one if `most_preferred` is set.
+fn pref_param(pref: u8, most_preferred: bool, version: VcardVersion) -> Option<Param<'static>> {
+    match version {
+        VcardVersion::V4_0 => Some(param("PREF", [pref.to_string()])),
+        VcardVersion::V3_0 | VcardVersion::V2_1 => most_preferred.then(|| param("TYPE", ["pref"])),
+    }
+}
+
+/// Formats a birthday for vCard 3.0 and 2.1, which only support complete dates.
+///
+/// Birthdays without a year are written the way Apple does it: with the year 1604 and an
+/// `X-APPLE-OMIT-YEAR` parameter. Other partial dates cannot be represented.
+fn legacy_birthday(birthday: &PartialDate) -> Option<(Vec<Param<'static>>, String)> {
+    match (birthday.year, birthday.month, birthday.day) {
+        (Some(year), Some(month), Some(day)) if year <= 9999 => {
+            Some((Vec::new(), format!("{year:04}-{month:02}-{day:02}")))
+        }
+        (None, Some(month), Some(day)) => Some((
+            vec![param("X-APPLE-OMIT-YEAR", ["1604"])],
+            format!("1604-{month:02}-{day:02}"),
+        )),
+        _ => None,
+    }
+}
+
+/// Formats the standard UTC offset of a time zone, e.g. "+01:00".
+fn standard_utc_offset(time_zone: Tz) -> String {
+    let offset = time_zone
+        .offset_from_utc_datetime(&Utc::now().naive_utc())
+        .base_utc_offset()
+        .num_minutes();
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("{sign}{:02}:{:02}", offset.abs() / 60, offset.abs() % 60)
+}
+
+/// Converts a vCard 4.0 content line into vCard 2.1 syntax.
+///
+/// Every type gets its own parameter, as lists of parameter values do not exist in vCard 2.1.
+/// Values that are not printable ASCII, e.g. notes spanning multiple lines, are encoded as
+/// quoted-printable UTF-8.
+fn to_vcard_2_1(contentline: Contentline<'static>) -> anyhow::Result<Contentline<'static>> {
+    let mut params = Vec::new();
+    for (name, values) in contentline_params(&contentline) {
+        if name == "TYPE" {
+            for value in values {
+                params.push(param("TYPE", [value.to_ascii_uppercase()]));
+            }
+        } else {
+            params.push(param_from_strings(name, values)?);
+        }
+    }
+
+    // Photos are the only binary values, all other values are text
+    if contentline.name.value() == "PHOTO" {
+        return Ok(Contentline {
+            params,
+            ..contentline
+        });
+    }
+
+    // Only semicolons are escaped in vCard 2.1
+    let mut text = String::new();
+    let mut chars = contentline.value.value().chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => text.push('\n'),
+            ('\\', Some(',' | '\\')) => text.push(chars.clone().next().expect("next char")),
+            ('\\', Some(';')) => text.push_str("\\;"),
+            _ => {
+                text.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+
+    let value = if text.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        text
+    } else {
+        if !text.is_ascii() {
+            params.push(param("CHARSET", ["UTF-8"]));
+        }
+        params.push(param("ENCODING", ["QUOTED-PRINTABLE"]));
+        encode_quoted_printable(&text)
+    };
+
+    Ok(Contentline {
+        group: contentline.group,
+        name: contentline.name,
+        params,
+        value: Value::new(value)?,
+    })
+}
+
+/// Encodes text as quoted-printable (see RFC 2045, section 6.7), with line breaks as CRLF.
+fn encode_quoted_printable(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    let bytes = text.as_bytes();
+    for (index, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'\n' => encoded.push_str("=0D=0A"),
+            // Spaces at the end of a line would be removed in transport
+            b' ' if matches!(bytes.get(index + 1), None | Some(b'\n')) => encoded.push_str("=20"),
+            b'=' => encoded.push_str("=3D"),
+            b' ' | b'!'..=b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("={byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Hands out the groups `item1`, `item2`, ... used to attach labels to properties, skipping the
+/// groups already used by the verbatim properties of a contact
+struct LabelGroups {
+    count: usize,
+    reserved: HashSet<String>,
+}
+
+impl LabelGroups {
+    fn new(contact: &Contact) -> Self {
+        LabelGroups {
+            count: 0,
+            reserved: contact
+                .vcard_properties
+                .iter()
+                .filter_map(|contentline| contentline.group.as_ref())
+                .map(|group| group.value().to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    fn next(&mut self) -> Identifier<'static> {
+        loop {
+            self.count += 1;
+            let group = format!("item{}", self.count);
+            if !self.reserved.contains(&group) {
+                return Identifier::new(group).expect("valid identifier");
+            }
+        }
+    }
+}
+
+/// Adds `contentline` to `contentlines`, followed by an `X-ABLabel` property if there is a label.
+///
+/// The label is associated with the property by putting both into the same group. This is not
+/// part of the vCard standard, but it is understood by most clients.
+fn push_with_label(
+    contentlines: &mut Vec<Contentline<'static>>,
+    mut contentline: Contentline<'static>,
+    label: Option<&str>,
+    label_groups: &mut LabelGroups,
+) -> anyhow::Result<()> {
+    let Some(label) = label else {
+        contentlines.push(contentline);
+        return Ok(());
+    };
+
+    let group = label_groups.next();
+    contentline.group = Some(group.clone());
+    contentlines.push(contentline);
+    contentlines.push(Contentline {
+        group: Some(group),
+        name: Identifier::new("X-ABLabel").expect("valid identifier"),
+        params: Vec::new(),
+        value: Value::new(label.to_owned())?,
+    });
+
+    Ok(())
+}
+
+/// Maps a relation type to a vCard `RELATED` type. All of them are registered in RFC 6350 except
+/// for managers.
+fn relation_type_to_vcard(relation_type: RelationType) -> Option<&'static str> {
+    Some(match relation_type {
+        RelationType::Spouse => "spouse",
+        RelationType::Child => "child",
+        RelationType::Parent => "parent",
+        RelationType::Sibling => "sibling",
+        RelationType::Kin => "kin",
+        RelationType::Friend => "friend",
+        RelationType::Colleague => "colleague",
+        RelationType::Neighbor => "neighbor",
+        RelationType::Manager => "x-manager",
+        RelationType::Assistant => "agent",
+        RelationType::Emergency => "emergency",
+        RelationType::Other => return None,
+    })
+}
+
+/// Maps a phone number type to a vCard `TYPE`. vCard 3.0 and 2.1 have fewer types, text messages
+/// are "msg" there.
+fn phone_number_type_to_vcard(
+    phone_number_type: PhoneNumberType,
+    version: VcardVersion,
+) -> &'static str {
+    match (phone_number_type, version) {
+        (PhoneNumberType::Text, VcardVersion::V3_0 | VcardVersion::V2_1) => return "msg",
+        (PhoneNumberType::Main, VcardVersion::V3_0 | VcardVersion::V2_1) => return "x-main",
+        (PhoneNumberType::Textphone, VcardVersion::V3_0 | VcardVersion::V2_1) => {
+            return "x-textphone"
+        }
+        _ => (),
+    }
+    match phone_number_type {
+        PhoneNumberType::Mobile => "cell",
+        PhoneNumberType::Home => "home",
+        PhoneNumberType::Work => "work",
+        PhoneNumberType::Fax => "fax",
+        PhoneNumberType::Pager => "pager",
+        // Registered in RFC 7852
+        PhoneNumberType::Main => "main-number",
+        PhoneNumberType::Voip => "x-voip",
+        PhoneNumberType::Text => "text",
+        PhoneNumberType::Voice => "voice",
+        PhoneNumberType::Video => "video",
+        PhoneNumberType::Textphone => "textphone",
+    }
+}
+
+// ========================================================================== //
+// =====> import <=========================================================== //
+// ========================================================================== //
+
+/// Parses the vCards in `input` into contacts.
+///
+/// Properties that are not modelled by [`Contact`], or that cannot be converted, are kept as they
+/// are and exported again verbatim. A warning is returned for every property that was understood
+/// but could not be converted.
+///
+/// The UID of contacts whose vCard has none is left empty, it is up to the caller to give them a
+/// new one (see [`Contact::new_uid`]).
+pub fn contacts_from_vcard(
+    input: &str,
+    default_region: Option<CountryCode>,
+) -> anyhow::Result<(Vec<Contact>, Vec<String>)> {
+    let mut contacts = Vec::new();
+    let mut warnings = Vec::new();
+    let mut vcard: Option<Vec<Contentline<'static>>> = None;
+    let mut vcard_2_1 = false;
+
+    for (line_number, line) in unfold(input) {
+        let contentline = parse_contentline(&line)
+            .and_then(|contentline| from_vcard_2_1(contentline, vcard_2_1))
+            .with_context(|| format!("Line {line_number}: Invalid content line"))?;
+        if contentline.name == "VERSION" {
+            vcard_2_1 = contentline.value == "2.1";
+        }
+        let is_vcard_delimiter = contentline.value.value().eq_ignore_ascii_case("VCARD");
+        match contentline.name.value().to_ascii_uppercase().as_str() {
+            "BEGIN" if is_vcard_delimiter => {
+                if vcard.is_some() {
+                    bail!("Line {line_number}: Nested vCards are not supported");
+                }
+                vcard = Some(Vec::new());
+                vcard_2_1 = false;
+            }
+            "END" if is_vcard_delimiter => {
+                let Some(contentlines) = vcard.take() else {
+                    bail!("Line {line_number}: END without BEGIN");
+                };
+                if is_group(&contentlines) {
+                    warnings.push(format!("Line {line_number}: Skipping group vCard"));
+                    continue;
+                }
+                let contact =
+                    contact_from_contentlines(contentlines, default_region, &mut warnings)
+                        .with_context(|| format!("Line {line_number}: Invalid vCard"))?;
+                contacts.push(contact);
+            }
+            _ => match &mut vcard {
+                Some(contentlines) => contentlines.push(contentline),
+                None => bail!("Line {line_number}: Content line outside of a vCard"),
+            },
+        }
+    }
+    if vcard.is_some() {
+        bail!("The last vCard is not terminated by END:VCARD");
+    }
+
+    Ok((contacts, warnings))
+}
+
+/// Checks if a vCard represents a group (see [`group_to_contentlines`]) rather than a person.
+fn is_group(contentlines: &[Contentline]) -> bool {
+    contentlines.iter().any(|contentline| {
+        let name = contentline.name.value();
+        (name.eq_ignore_ascii_case("KIND") || name.eq_ignore_ascii_case("X-ADDRESSBOOKSERVER-KIND"))
+            && contentline.value.value().eq_ignore_ascii_case("group")
+    })
+}
+
+/// Joins folded lines (see RFC 6350, section 3.2), returning every logical line along with the
+/// number of the line it starts on.
+///
+/// vCard 2.1 folds lines before whitespace, which is kept when unfolding them. Quoted-printable
+/// values of vCard 2.1 are continued on the next line if they end with a soft line break ("="),
+/// which is removed.
+fn unfold(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut soft_line_break = false;
+    let mut vcard_2_1 = false;
+    for (index, line) in input.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (_, Some((_, previous))) if soft_line_break => previous.push_str(line),
+            (Some(_), Some((_, previous))) if vcard_2_1 => previous.push_str(line),
+            (Some(continuation), Some((_, previous))) => previous.push_str(continuation),
+            _ if line.is_empty() => (),
+            _ => {
+                match lines.last() {
+                    Some((_, previous)) if previous.eq_ignore_ascii_case("BEGIN:VCARD") => {
+                        vcard_2_1 = false;
+                    }
+                    Some((_, previous)) if previous.eq_ignore_ascii_case("VERSION:2.1") => {
+                        vcard_2_1 = true;
+                    }
+                    _ => (),
+                }
+                lines.push((index + 1, line.to_owned()));
+            }
+        }
+
+        soft_line_break = false;
+        if let Some((_, line)) = lines.last_mut() {
+            let quoted_printable = line
+                .split(':')
+                .next()
+                .is_some_and(|start| start.to_ascii_uppercase().contains("QUOTED-PRINTABLE"));
+            if quoted_printable && line.ends_with('=') {
+                line.pop();
+                soft_line_break = true;
+            }
+        }
+    }
+    lines
+}
+
+/// Converts a vCard 2.1 content line into vCard 4.0 syntax, reversing [`to_vcard_2_1`].
+///
+/// Quoted-printable values are decoded, removing the `ENCODING` and `CHARSET` parameters. As only
+/// semicolons are escaped in vCard 2.1, commas, backslashes and line breaks are escaped as in
+/// vCard 4.0, so that they do not change the components of structured values. Quoted-printable
+/// values are converted in any version, other content lines of other versions are returned
+/// unchanged.
+///
+/// Values are expected to be UTF-8, values that are not are read as ISO 8859-1.
+fn from_vcard_2_1(
+    contentline: Contentline<'static>,
+    vcard_2_1: bool,
+) -> anyhow::Result<Contentline<'static>> {
+    let quoted_printable = param_values(&contentline, "ENCODING")
+        .iter()
+        .any(|encoding| encoding.eq_ignore_ascii_case("QUOTED-PRINTABLE"));
+    // Photos are the only binary values, all other values are text
+    if !quoted_printable && (!vcard_2_1 || contentline.name == "PHOTO") {
+        return Ok(contentline);
+    }
+    if !quoted_printable {
+        let value = escape_vcard_2_1_text(contentline.value.value());
+        return Ok(Contentline {
+            value: Value::new(value)?,
+            ..contentline
+        });
+    }
+
+    let encoded = contentline.value.value().as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        if encoded[index] == b'=' {
+            let byte = encoded
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                .context("Invalid quoted-printable value")?;
+            bytes.push(byte);
+            index += 3;
+        } else {
+            bytes.push(encoded[index]);
+            index += 1;
+        }
+    }
+    let text = String::from_utf8(bytes).unwrap_or_else(|error| {
+        error
+            .into_bytes()
+            .iter()
+            .map(|&byte| byte as char)
+            .collect()
+    });
+
+    let value = escape_vcard_2_1_text(&text);
+    let params = contentline_params(&contentline)
+        .into_iter()
+        .filter(|(name, _)| name != "ENCODING" && name != "CHARSET")
+        .map(|(name, values)| param_from_strings(name, values))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Contentline {
+        params,
+        value: Value::new(value)?,
+        ..contentline
+    })
+}
+
+/// Escapes a vCard 2.1 text value like in vCard 4.0, keeping escaped semicolons.
+fn escape_vcard_2_1_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.next_if_eq(&';').is_some() => escaped.push_str("\\;"),
+            '\\' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parses a single unfolded content line.
+///
+/// Parameters without a name, as used by vCard 2.1 (e.g. `TEL;CELL:...`), are treated as `TYPE`
+/// parameters.
+pub fn parse_contentline(line: &str) -> anyhow::Result<Contentline<'static>> {
+    let name_end = line
+        .find([';', ':'])
+        .context("Missing ':' between name and value")?;
+    let (group, name) = match line[..name_end].split_once('.') {
+        Some((group, name)) => (Some(Identifier::new(group.to_owned())?), name),
+        None => (None, &line[..name_end]),
+    };
+    let name = Identifier::new(name.to_owned())?;
+
+    let mut params = Vec::new();
+    let mut rest = &line[name_end..];
+    while let Some(param) = rest.strip_prefix(';') {
+        let param_name_end = param
+            .find(['=', ';', ':'])
+            .context("Missing ':' between name and value")?;
+        let param_name = &param[..param_name_end];
+        rest = &param[param_name_end..];
+
+        let Some(mut values_rest) = rest.strip_prefix('=') else {
+            params.push(Param::new(
+                Identifier::new("TYPE")?,
+                vec![ParamValue::new(param_name.to_owned())?],
+            )?);
+            continue;
+        };
+        let mut values = Vec::new();
+        loop {
+            let (value, after_value) = match values_rest.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted
+                        .find('"')
+                        .context("Unterminated quoted parameter value")?;
+                    (&quoted[..end], &quoted[end + 1..])
+                }
+                None => {
+                    let end = values_rest
+                        .find([',', ';', ':'])
+                        .context("Missing ':' between name and value")?;
+                    (&values_rest[..end], &values_rest[end..])
+                }
+            };
+            values.push(ParamValue::new(decode_param_value(value))?);
+            match after_value.strip_prefix(',') {
+                Some(next) => values_rest = next,
+                None => {
+                    rest = after_value;
+                    break;
+                }
+            }
+        }
+        params.push(Param::new(Identifier::new(param_name.to_owned())?, values)?);
+    }
+
+    let value = rest
+        .strip_prefix(':')
+        .context("Missing ':' between name and value")?;
+
+    Ok(Contentline {
+        group,
+        name,
+        params,
+        value: Value::new(value.to_owned())?,
+    })
+}
+
+/// Formats a content line the way it appears in a vCard, without folding.
+pub fn format_contentline(contentline: &Contentline) -> String {
+    contentline.to_string()
+}
+
+fn contact_from_contentlines(
+    contentlines: Vec<Contentline<'static>>,
+    default_region: Option<CountryCode>,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<Contact> {
+    let mut contact = Contact::default();
+    let mut formatted_name = None;
+
+    // Labels are attached to properties by putting both into the same group
+    let labels = contentlines
+        .iter()
+        .filter(|contentline| contentline.name.value().eq_ignore_ascii_case("X-ABLabel"))
+        .filter_map(|contentline| {
+            let group = contentline.group.as_ref()?.value().to_ascii_lowercase();
+            let label = unescape_text(contentline.value.value());
+            // Apple marks its predefined labels like this: "_$!<Mobile>!$_"
+            let label = match label
+                .strip_prefix("_$!<")
+                .and_then(|l| l.strip_suffix(">!$_"))
+            {
+                Some(predefined) => predefined.to_owned(),
+                None => label,
+            };
+            Some((group, label))
+        })
+        .collect::<HashMap<_, _>>();
+    let label_of = |contentline: &Contentline| {
+        let group = contentline.group.as_ref()?.value().to_ascii_lowercase();
+        labels.get(&group).cloned()
+    };
+
+    // Phone numbers in national format belong to the country of the contact, which is only known
+    // once all addresses have been read
+    let (telephones, others): (Vec<_>, Vec<_>) = contentlines
+        .into_iter()
+        .partition(|contentline| contentline.name.value().eq_ignore_ascii_case("TEL"));
+
+    let has_addresses = others
+        .iter()
+        .any(|contentline| contentline.name.value().eq_ignore_ascii_case("ADR"));
+    let mut languages = Vec::new();
+    let mut label_contentlines = Vec::new();
+    let mut labelled_groups = HashSet::new();
+    let mut unknown = Vec::new();
+    for contentline in others {
+        let name = contentline.name.value().to_ascii_uppercase();
+        let result = match name.as_str() {
+            "VERSION" => continue,
+            // Formatted addresses (vCard 3.0 and 2.1) are derived from the addresses on export
+            "LABEL" if has_addresses => continue,
+            "X-ABLABEL" => {
+                label_contentlines.push(contentline);
+                continue;
+            }
+            "FN" => {
+                formatted_name = Some(unescape_text(contentline.value.value()));
+                continue;
+            }
+            _ if !has_known_params(&name, &contentline) => Ok(false),
+            "LANG" => {
+                let pref = pref_of(&contentline)?;
+                let language = contentline.value.value().to_owned();
+                validate_language_tag(&language).map(|()| {
+                    languages.push((pref.unwrap_or(u8::MAX), language));
+                    true
+                })
+            }
+            // Only addresses and phone numbers have labels, other labelled properties are kept
+            // unchanged so that their labels are not lost
+            "ADR" => import_property(&mut contact, &contentline, label_of(&contentline)),
+            _ if label_of(&contentline).is_some() => Ok(false),
+            _ => import_property(&mut contact, &contentline, None),
+        };
+        match result {
+            Ok(true) => {
+                if let Some(group) = &contentline.group {
+                    labelled_groups.insert(group.value().to_ascii_lowercase());
+                }
+            }
+            Ok(false) => unknown.push(contentline),
+            Err(error) => {
+                warnings.push(format!(
+                    "Keeping {name} unchanged, because it could not be read: {error:#}"
+                ));
+                unknown.push(contentline);
+            }
+        }
+    }
+
+    let region = contact
+        .addresses
+        .iter()
+        .min_by_key(|address| address.pref.unwrap_or(u8::MAX))
+        .map(|address| address.country)
+        .or(default_region);
+    for contentline in telephones {
+        if !has_known_params("TEL", &contentline) {
+            unknown.push(contentline);
+            continue;
+        }
+        match import_phone_number(&contentline, label_of(&contentline), region) {
+            Ok(phone_number) => {
+                if let Some(group) = &contentline.group {
+                    labelled_groups.insert(group.value().to_ascii_lowercase());
+                }
+                contact.phone_numbers.push(phone_number);
+            }
+            Err(error) => {
+                warnings.push(format!(
+                    "Keeping TEL unchanged, because it could not be read: {error:#}"
+                ));
+                unknown.push(contentline);
+            }
+        }
+    }
+
+    languages.sort_by_key(|(pref, _)| *pref);
+    contact.languages = languages
+        .into_iter()
+        .map(|(_, language)| language)
+        .collect();
+
+    // Labels of properties that are kept unchanged must be kept as well
+    unknown.extend(label_contentlines.into_iter().filter(|contentline| {
+        contentline
+            .group
+            .as_ref()
+            .is_none_or(|group| !labelled_groups.contains(&group.value().to_ascii_lowercase()))
+    }));
+    contact.vcard_properties = unknown;
+
+    if contact.name.validate().is_err() {
+        if let Some(formatted_name) = formatted_name {
+            contact.name.first = formatted_name;
+        }
+    }
+    contact.name.validate()?;
+
+    Ok(contact)
+}
+
+/// Converts a property into the corresponding field of `contact`.
+///
+/// Returns `false` if the property is not modelled by [`Contact`], or if it cannot be represented
+/// without losing information, e.g. because the field is already set.
+fn import_property(
+    contact: &mut Contact,
+    contentline: &Contentline,
+    label: Option<String>,
+) -> anyhow::Result<bool> {
+    let value = contentline.value.value();
+    let types = param_values(contentline, "TYPE");
+
+    match contentline.name.value().to_ascii_uppercase().as_str() {
+        "UID" if contact.uid.is_empty() => {
+            validate_uri(value)?;
+            contact.uid = value.to_owned();
+        }
+        "N" => {
+            let components = split_unescaped(value, ';');
+            if components.len() > 5 || !contact.name.last.is_empty() {
+                return Ok(false);
+            }
+            let component = |index: usize| components.get(index).copied().unwrap_or_default();
+            let list = |index: usize| {
+                split_unescaped(component(index), ',')
+                    .into_iter()
+                    .map(unescape_text)
+                    .filter(|value| !value.is_empty())
+                    .collect::<Vec<_>>()
+            };
+            contact.name.last = unescape_text(component(0));
+            contact.name.first = unescape_text(component(1));
+            contact.name.middle = list(2);
+            contact.name.prefixes = list(3);
+            contact.name.suffixes = list(4);
+            contact.name.sort_as = param_values(contentline, "SORT-AS").into_iter().next();
+        }
+        "SORT-STRING" if contact.name.sort_as.is_none() => {
+            contact.name.sort_as = Some(unescape_text(value));
+        }
+        "NICKNAME" if contact.name.nickname.is_none() => {
+            contact.name.nickname = Some(unescape_text(value));
+        }
+        "BDAY" if contact.birthday.is_none() => {
+            let mut birthday = PartialDate::from_vcard_string_repr(value)?;
+            // Apple's way of writing birthdays without a year in vCard 3.0
+            if !param_values(contentline, "X-APPLE-OMIT-YEAR").is_empty() {
+                birthday.year = None;
+            }
+            contact.birthday = Some(birthday);
+        }
+        "EMAIL" => {
+            let ty = match known_types(&types, &["home", "work", "internet"])?.as_slice() {
+                [] => EmailAddressType::Other,
+                ["home"] => EmailAddressType::Home,
+                ["work"] => EmailAddressType::Work,
+                _ => return Ok(false),
+            };
+            let email_address = EmailAddress {
+                address: unescape_text(value),
+                ty,
+                pref: pref_of(contentline)?.is_some(),
+            };
+            email_address.validate()?;
+            contact.email_addresses.push(email_address);
+        }
+        "ADR" => {
+            let ty = match known_types(&types, &["home", "work", "postal", "parcel"])?.as_slice() {
+                [] => AddressType::Other,
+                ["home"] => AddressType::Home,
+                ["work"] => AddressType::Work,
+                _ => return Ok(false),
+            };
+            let components = split_unescaped(value, ';')
+                .into_iter()
+                .map(unescape_text)
+                .collect::<Vec<_>>();
+            let [po_box, extended, street, locality, region, postal_code, country] =
+                <[String; 7]>::try_from(components)
+                    .map_err(|_| anyhow!("Addresses must consist of 7 components"))?;
+            let country = country_from_name(&country)
+                .with_context(|| format!("Unknown country \"{country}\""))?;
+            let non_empty = |component: String| (!component.is_empty()).then_some(component);
+            let (street, number) = Address::split_street_line(&street, country);
+            contact.addresses.push(Address {
+                street,
+                number,
+                extended: non_empty(extended),
+                po_box: non_empty(po_box),
+                locality,
+                region: non_empty(region),
+                postal_code,
+                country,
+                ty,
+                label,
+                pref: pref_of(contentline)?,
+            });
+        }
+        "ORG" if contact.organization.is_none() => {
+            let components = split_unescaped(value, ';')
+                .into_iter()
+                .map(unescape_text)
+                .collect::<Vec<_>>();
+            let Ok([name, department]) =
+                <[String; 2]>::try_from(components.clone()).or_else(|_| {
+                    <[String; 1]>::try_from(components).map(|[name]| [name, String::new()])
+                })
+            else {
+                return Ok(false);
+            };
+            contact.organization = Some(Organization {
+                name,
+                department: (!department.is_empty()).then_some(department),
+            });
+        }
+        "TITLE" if contact.title.is_none() => contact.title = Some(unescape_text(value)),
+        "ROLE" if contact.role.is_none() => contact.role = Some(unescape_text(value)),
+        "NOTE" if contact.notes.is_none() => contact.notes = Some(unescape_text(value)),
+        "CATEGORIES" => {
+            for tag in split_unescaped(value, ',').into_iter().map(unescape_text) {
+                validate_tag(&tag)?;
+                if !contact.tags.contains(&tag) {
+                    contact.tags.push(tag);
+                }
+            }
+        }
+        "URL" => {
+            let ty = match known_types(&types, &["home", "work", "x-blog"])?.as_slice() {
+                [] => WebsiteType::Other,
+                ["home"] => WebsiteType::Home,
+                ["work"] => WebsiteType::Work,
+                ["x-blog"] => WebsiteType::Blog,
+                _ => return Ok(false),
+            };
+            validate_uri(value)?;
+            contact.websites.push(Website {
+                url: value.to_owned(),
+                ty,
+            });
+        }
+        "GEO" if contact.geo.is_none() => {
+            // vCard 4.0 uses a geo URI, vCard 3.0 two components
+            let coordinates = value
+                .strip_prefix("geo:")
+                .map(|uri| {
+                    uri.split(';')
+                        .next()
+                        .unwrap_or_default()
+                        .split(',')
+                        .collect()
+                })
+                .unwrap_or_else(|| split_unescaped(value, ';'));
+            let [latitude, longitude] = <[&str; 2]>::try_from(coordinates)
+                .map_err(|_| anyhow!("Invalid coordinates \"{value}\""))?;
+            let geo = Geo {
+                latitude: f64::from_str(latitude.trim())
+                    .with_context(|| format!("Invalid latitude \"{latitude}\""))?,
+                longitude: f64::from_str(longitude.trim())
+                    .with_context(|| format!("Invalid longitude \"{longitude}\""))?,
+            };
+            geo.validate()?;
+            contact.geo = Some(geo);
+        }
+        // Time zones given as UTC offsets cannot be represented
+        "TZ" if contact.time_zone.is_none() => match Tz::from_str(value) {
+            Ok(time_zone) => contact.time_zone = Some(time_zone),
+            Err(_) => return Ok(false),
+        },
+        "GENDER" if contact.gender.is_none() => {
+            let components = split_unescaped(value, ';');
+            let sex = match components.first().copied().unwrap_or_default() {
+                "" => None,
+                "M" | "m" => Some(Sex::Male),
+                "F" | "f" => Some(Sex::Female),
+                "O" | "o" => Some(Sex::Other),
+                "N" | "n" => Some(Sex::NotApplicable),
+                "U" | "u" => Some(Sex::Unknown),
+                sex => bail!("Invalid sex \"{sex}\""),
+            };
+            let identity = components
+                .get(1)
+                .map(|identity| unescape_text(identity))
+                .filter(|identity| !identity.is_empty());
+            if sex.is_none() && identity.is_none() {
+                return Ok(false);
+            }
+            contact.gender = Some(Gender { sex, identity });
+        }
+        "RELATED" => {
+            let ty = match types.as_slice() {
+                [] => RelationType::Other,
+                [ty] => relation_type_from_vcard(ty)
+                    .ok_or_else(|| anyhow!("Unknown relation type \"{ty}\""))?,
+                _ => return Ok(false),
+            };
+            let is_text = param_values(contentline, "VALUE")
+                .iter()
+                .any(|value_type| value_type.eq_ignore_ascii_case("text"));
+            let target = if is_text {
+                RelationTarget::Person(unescape_text(value))
+            } else {
+                validate_uri(value)?;
+                RelationTarget::Contact(value.to_owned())
+            };
+            contact.relations.push(Relation { ty, target });
+        }
+        // Photos are only imported if they are in the photos directory of the store, see
+        // `PhotoMode::Store`
+        "PHOTO" if contact.photo.is_none() => {
+            let Some(file_name) = value.strip_prefix(STORE_PHOTO_PREFIX) else {
+                return Ok(false);
+            };
+            validate_photo_file_name(file_name)?;
+            contact.photo = Some(file_name.to_owned());
+        }
+        "IMPP" => {
+            let impp_handle = ImppHandle::from_uri(value);
+            // The name of another service would be lost
+            let service_types = param_values(contentline, "X-SERVICE-TYPE");
+            if !service_types
+                .iter()
+                .all(|service_type| service_type.eq_ignore_ascii_case(impp_handle.service.name()))
+            {
+                return Ok(false);
+            }
+            impp_handle.validate()?;
+            contact.impp.push(impp_handle);
+        }
+        "X-SOCIALPROFILE" => {
+            let service = match types.as_slice() {
+                [ty] => match ty.to_ascii_lowercase().as_str() {
+                    "mastodon" => SocialService::Mastodon,
+                    "github" => SocialService::Github,
+                    "linkedin" => SocialService::Linkedin,
+                    "twitter" => SocialService::Twitter,
+                    "other" => SocialService::Other,
+                    _ => return Ok(false),
+                },
+                _ => return Ok(false),
+            };
+            let Some(social_profile) = SocialProfile::from_url(service, value) else {
+                return Ok(false);
+            };
+            let user = param_values(contentline, "X-USER");
+            if user.iter().any(|user| user != social_profile.user()) {
+                return Ok(false);
+            }
+            social_profile.validate()?;
+            contact.social_profiles.push(social_profile);
+        }
+        // Custom fields, as long as their values are not structured
+        name if name.starts_with("X-")
+            && split_unescaped(value, ';').len() == 1
+            && split_unescaped(value, ',').len() == 1 =>
+        {
+            let key = name["X-".len()..].to_ascii_lowercase();
+            // Reserved names are kept verbatim
+            if contact.extra.contains_key(&key) || validate_extra_key(&key).is_err() {
+                return Ok(false);
+            }
+            contact.extra.insert(key, unescape_text(value));
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn import_phone_number(
+    contentline: &Contentline,
+    label: Option<String>,
+    region: Option<CountryCode>,
+) -> anyhow::Result<PhoneNumber> {
+    let value = contentline.value.value();
+    let number = value.strip_prefix("tel:").unwrap_or(value);
+    let types = param_values(contentline, "TYPE")
+        .iter()
+        .filter(|ty| !ty.eq_ignore_ascii_case("pref"))
+        .map(|ty| {
+            phone_number_type_from_vcard(ty)
+                .ok_or_else(|| anyhow!("Unknown phone number type \"{ty}\""))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    PhoneNumber::parse(
+        number.to_owned(),
+        types,
+        label,
+        pref_of(contentline)?,
+        region,
+    )
+}
+
+/// Checks if all parameters of a property are represented by the field it is imported into.
+///
+/// Properties with other parameters are kept unchanged, so that the parameters are not lost.
+fn has_known_params(name: &str, contentline: &Contentline) -> bool {
+    let known: &[&str] = match name {
+        "N" => &["SORT-AS"],
+        "BDAY" => &["X-APPLE-OMIT-YEAR"],
+        "EMAIL" | "LANG" | "TEL" | "URL" => &["TYPE", "PREF"],
+        "ADR" => &["TYPE", "PREF", "LABEL"],
+        "RELATED" => &["TYPE"],
+        "X-SOCIALPROFILE" => &["TYPE", "X-USER"],
+        "IMPP" => &["X-SERVICE-TYPE"],
+        _ => &[],
+    };
+    contentline_params(contentline).iter().all(|(param, _)| {
+        // The value type follows from the field, and all values are read as UTF-8
+        ["VALUE", "CHARSET"]
+            .iter()
+            .chain(known)
+            .any(|known| known.eq_ignore_ascii_case(param))
+    })
+}
+
+/// The names and values of the parameters of a content line
+///
+/// `ical_vcard` does not give access to the parameters of a parsed content line, so they are read
+/// back from the formatted content line, in which the names are uppercase.
+fn contentline_params(contentline: &Contentline) -> Vec<(String, Vec<String>)> {
+    let line = Contentline {
+        group: None,
+        name: contentline.name.clone(),
+        params: contentline.params.clone(),
+        value: Value::new("").expect("valid value"),
+    }
+    .to_string();
+    // Only the parameters are left, each of them starting with ';'
+    let mut rest = &line[contentline.name.value().len()..line.len() - 1];
+
+    let mut params = Vec::new();
+    while let Some(param) = rest.strip_prefix(';') {
+        let (name, mut values_rest) = param.split_once('=').expect("parameter with values");
+        let mut values = Vec::new();
+        loop {
+            // Quoted values cannot contain quotes, they are encoded (see RFC 6868)
+            let (value, after_value) = match values_rest.strip_prefix('"') {
+                Some(quoted) => quoted.split_once('"').expect("terminated quoted value"),
+                None => {
+                    values_rest.split_at(values_rest.find([',', ';']).unwrap_or(values_rest.len()))
+                }
+            };
+            values.push(decode_param_value(value));
+            match after_value.strip_prefix(',') {
+                Some(next) => values_rest = next,
+                None => {
+                    rest = after_value;
+                    break;
+                }
+            }
+        }
+        params.push((name.to_owned(), values));
+    }
+    params
+}
+
+/// The values of all parameters with the given name, split at commas
+fn param_values(contentline: &Contentline, name: &str) -> Vec<String> {
+    contentline_params(contentline)
+        .into_iter()
+        .filter(|(param, _)| param.eq_ignore_ascii_case(name))
+        .flat_map(|(_, values)| values)
+        .flat_map(|value| {
+            value
+                .split(',')
+                .map(|value| value.to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Lowercases the types and checks that all of them are known. `pref` is always allowed and
+/// removed, as it is handled by [`pref_of`].
+fn known_types<'a>(types: &[String], known: &[&'a str]) -> anyhow::Result<Vec<&'a str>> {
+    let mut result = Vec::new();
+    for ty in types {
+        if ty.eq_ignore_ascii_case("pref") {
+            continue;
+        }
+        let Some(known) = known.iter().find(|known| known.eq_ignore_ascii_case(ty)) else {
+            bail!("Unknown type \"{ty}\"");
+        };
+        // Types that carry no information in tactful's model are dropped
+        if !matches!(*known, "internet" | "postal" | "parcel") {
+            result.push(*known);
+        }
+    }
+    Ok(result)
+}
+
+/// The preference of a property, given either by a `PREF` parameter or by a `pref` type
+/// (vCard 3.0)
+fn pref_of(contentline: &Contentline) -> anyhow::Result<Option<u8>> {
+    if let Some(pref) = param_values(contentline, "PREF").first() {
+        let pref = u8::from_str(pref).with_context(|| format!("Invalid preference \"{pref}\""))?;
+        validate_pref(Some(pref))?;
+        return Ok(Some(pref));
+    }
+    let preferred = param_values(contentline, "TYPE")
+        .iter()
+        .any(|ty| ty.eq_ignore_ascii_case("pref"));
+    Ok(preferred.then_some(1))
+}
+
+/// Reverses [`relation_type_to_vcard`].
+fn relation_type_from_vcard(relation_type: &str) -> Option<RelationType> {
+    Some(match relation_type.to_ascii_lowercase().as_str() {
+        "spouse" => RelationType::Spouse,
+        "child" => RelationType::Child,
+        "parent" => RelationType::Parent,
+        "sibling" => RelationType::Sibling,
+        "kin" => RelationType::Kin,
+        "friend" => RelationType::Friend,
+        "colleague" => RelationType::Colleague,
+        "neighbor" => RelationType::Neighbor,
+        "x-manager" => RelationType::Manager,
+        "agent" => RelationType::Assistant,
+        "emergency" => RelationType::Emergency,
+        _ => return None,
+    })
+}
+
+fn phone_number_type_from_vcard(phone_number_type: &str) -> Option<PhoneNumberType> {
+    Some(match phone_number_type.to_ascii_lowercase().as_str() {
+        "cell" => PhoneNumberType::Mobile,
+        "home" => PhoneNumberType::Home,
+        "work" => PhoneNumberType::Work,
+        "fax" => PhoneNumberType::Fax,
+        "pager" => PhoneNumberType::Pager,
+        "main-number" | "x-main" => PhoneNumberType::Main,
+        "x-voip" => PhoneNumberType::Voip,
+        "text" | "msg" => PhoneNumberType::Text,
+        "voice" => PhoneNumberType::Voice,
+        "video" => PhoneNumberType::Video,
+        "textphone" | "x-textphone" => PhoneNumberType::Textphone,
+        _ => return None,
+    })
+}
+
+/// Finds a country by its name, as written by [`contacts_to_vcard`], or by its ISO 3166-1 code.
+fn country_from_name(name: &str) -> Option<CountryCode> {
+    let name = name.trim();
+    country_codes::ALL
+        .iter()
+        .find(|country| country.name.eq_ignore_ascii_case(name))
+        .copied()
+        .or_else(|| country_codes::from_alpha2(&name.to_ascii_uppercase()))
+        .or_else(|| country_codes::from_alpha3(&name.to_ascii_uppercase()))
+}
+
+/// Splits a property value at unescaped separators. The parts are not unescaped.
+fn split_unescaped(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == separator => {
+                parts.push(&value[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Reverses [`escape_component`].
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Decodes a parameter value that may contain newlines or double quotes (see RFC 6868).
+fn decode_param_value(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('^', Some('^')) => decoded.push('^'),
+            ('^', Some('n')) => decoded.push('\n'),
+            ('^', Some('\'')) => decoded.push('"'),
+            _ => {
+                decoded.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::json};
+
+    fn import(vcard: &str) -> Contact {
+        let (mut contacts, _) = contacts_from_vcard(vcard, None).unwrap();
+        assert_eq!(contacts.len(), 1);
+        contacts.remove(0)
+    }
+
+    #[test]
+    fn unfold_joins_continuation_lines() {
+        let lines = unfold("BEGIN:VCARD\r\nNOTE:one\r\n  two\r\n\t three\r\n\r\nEND:VCARD\r\n");
+        assert_eq!(
+            lines,
+            [
+                (1, "BEGIN:VCARD".to_owned()),
+                (2, "NOTE:one two three".to_owned()),
+                (6, "END:VCARD".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_contentline_reads_group_params_and_value() {
+        let contentline =
+            parse_contentline("item1.ADR;TYPE=home,work;LABEL=\"a;b:c\":;;Street 1").unwrap();
+        assert_eq!(contentline.group.as_ref().unwrap().value(), "item1");
+        assert_eq!(contentline.name.value(), "ADR");
+        assert_eq!(contentline.value.value(), ";;Street 1");
+        assert_eq!(
+            contentline_params(&contentline),
+            [
+                (
+                    "TYPE".to_owned(),
+                    vec!["home".to_owned(), "work".to_owned()]
+                ),
+                ("LABEL".to_owned(), vec!["a;b:c".to_owned()])
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_contentline_treats_nameless_params_as_types() {
+        let contentline = parse_contentline("TEL;CELL;PREF:+41 79 123 45 67").unwrap();
+        assert_eq!(param_values(&contentline, "TYPE"), ["CELL", "PREF"]);
+        assert_eq!(contentline.value.value(), "+41 79 123 45 67");
+    }
+
+    #[test]
+    fn parse_contentline_rejects_missing_value() {
+        assert!(parse_contentline("NOTE").is_err());
+        assert!(parse_contentline("NOTE;TYPE=\"home:x").is_err());
+    }
+
+    #[test]
+    fn partial_dates_are_parsed_in_all_vcard_formats() {
+        let parse = |date| {
+            let date = PartialDate::from_vcard_string_repr(date).unwrap();
+            (date.year, date.month, date.day)
+        };
+        assert_eq!(parse("19800401"), (Some(1980), Some(4), Some(1)));
+        assert_eq!(parse("1980-04-01"), (Some(1980), Some(4), Some(1)));
+        assert_eq!(parse("1980-04"), (Some(1980), Some(4), None));
+        assert_eq!(parse("1980"), (Some(1980), None, None));
+        assert_eq!(parse("--0401"), (None, Some(4), Some(1)));
+        assert_eq!(parse("--04"), (None, Some(4), None));
+        assert_eq!(parse("---01"), (None, None, Some(1)));
+        assert!(PartialDate::from_vcard_string_repr("01.04.1980").is_err());
+        assert!(PartialDate::from_vcard_string_repr("19800431").is_err());
+    }
+
+    #[test]
+    fn import_splits_street_and_number() {
+        let contact = import(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Anna\r\n\
+             ADR;TYPE=home:;;Bahnhofstrasse 12a;Zürich;;8001;Switzerland\r\n\
+             ADR;TYPE=work:;;1600 Pennsylvania Avenue;Washington;DC;20500;US\r\n\
+             END:VCARD\r\n",
+        );
+        let streets = contact
+            .addresses
+            .iter()
+            .map(|address| (address.street.as_str(), address.number.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            streets,
+            [("Bahnhofstrasse", "12a"), ("Pennsylvania Avenue", "1600")]
+        );
+    }
+
+    #[test]
+    fn import_keeps_properties_with_unknown_params() {
+        let contact = import(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Anna\r\n\
+             EMAIL;TYPE=home:anna@example.com\r\n\
+             EMAIL;X-FOO=1:work@example.com\r\n\
+             END:VCARD\r\n",
+        );
+        assert_eq!(contact.email_addresses.len(), 1);
+        let kept = contact
+            .vcard_properties
+            .iter()
+            .map(format_contentline)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, ["EMAIL;X-FOO=1:work@example.com"]);
+    }
+
+    fn contact(json: &str) -> Contact {
+        let (mut contacts, diagnostics) = json::contacts_from_json_lenient(json, None);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        contacts.remove(0)
+    }
+
+    fn export(contact: &Contact, version: VcardVersion) -> String {
+        let options = VcardOptions {
+            groups: false,
+            photo_mode: PhotoMode::Embed,
+            photos_path: PathBuf::new(),
+            derive_time_zone: false,
+            version,
+        };
+        String::from_utf8(contact_to_vcard(contact, &options).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn encode_quoted_printable_escapes_line_breaks_and_non_ascii() {
+        assert_eq!(encode_quoted_printable("a=b"), "a=3Db");
+        assert_eq!(encode_quoted_printable("one\ntwo"), "one=0D=0Atwo");
+        assert_eq!(encode_quoted_printable("Zürich"), "Z=C3=BCrich");
+        assert_eq!(
+            encode_quoted_printable("end \nnext "),
+            "end=20=0D=0Anext=20"
+        );
+    }
+
+    #[test]
+    fn legacy_birthday_omits_missing_year_like_apple() {
+        let date = |year, month, day| PartialDate { year, month, day };
+        let (params, value) = legacy_birthday(&date(Some(1980), Some(4), Some(1))).unwrap();
+        assert!(params.is_empty());
+        assert_eq!(value, "1980-04-01");
+        let (params, value) = legacy_birthday(&date(None, Some(4), Some(1))).unwrap();
+        assert_eq!(params, [param("X-APPLE-OMIT-YEAR", ["1604"])]);
+        assert_eq!(value, "1604-04-01");
+        assert!(legacy_birthday(&date(Some(1980), Some(4), None)).is_none());
+        assert!(legacy_birthday(&date(None, None, Some(1))).is_none());
+    }
+
+    #[test]
+    fn to_vcard_2_1_splits_types_and_encodes_text() {
+        let contentline = parse_contentline("TEL;TYPE=cell,voice:+41791234567").unwrap();
+        let contentline = to_vcard_2_1(contentline).unwrap();
+        assert_eq!(
+            format_contentline(&contentline),
+            "TEL;TYPE=CELL;TYPE=VOICE:+41791234567"
+        );
+
+        let contentline = parse_contentline("NOTE:a\\, b\\; c\\nZürich").unwrap();
+        let contentline = to_vcard_2_1(contentline).unwrap();
+        assert_eq!(
+            format_contentline(&contentline),
+            "NOTE;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:a, b\\; c=0D=0AZ=C3=BCrich"
+        );
+    }
+
+    #[test]
+    fn vcard_2_1_wraps_quoted_printable_values_with_soft_line_breaks() {
+        let notes = "Zürich ".repeat(20);
+        let contact = contact(&format!(
+            r#"[{{"name": {{"first": "Anna"}}, "notes": "{notes}"}}]"#
+        ));
+        let vcard = export(&contact, VcardVersion::V2_1);
+        let note = vcard
+            .split("\r\n")
+            .skip_while(|line| !line.starts_with("NOTE"))
+            .take_while(|line| !line.starts_with("UID"))
+            .collect::<Vec<_>>();
+        assert!(note.len() > 1);
+        for line in &note {
+            assert!(line.len() <= 76, "{line}");
+            assert!(!line.starts_with(' '), "{line}");
+        }
+        for line in &note[..note.len() - 1] {
+            assert!(line.ends_with('='), "{line}");
+        }
+
+        let (contacts, _) = contacts_from_vcard(&vcard, None).unwrap();
+        assert_eq!(contacts[0].notes.as_deref(), Some(notes.as_str()));
+    }
+
+    #[test]
+    fn vcard_2_1_folds_lines_before_whitespace() {
+        let notes = "one two three four five six seven eight nine ten ".repeat(4);
+        let notes = notes.trim_end();
+        let contact = contact(&format!(
+            r#"[{{"name": {{"first": "Anna"}}, "notes": "{notes}"}}]"#
+        ));
+        let vcard = export(&contact, VcardVersion::V2_1);
+        let note = vcard
+            .split("\r\n")
+            .skip_while(|line| !line.starts_with("NOTE"))
+            .take_while(|line| !line.starts_with("UID"))
+            .collect::<Vec<_>>();
+        assert!(note.len() > 1);
+        for line in &note {
+            assert!(line.len() <= 75, "{line}");
+        }
+        for line in &note[1..] {
+            assert!(line.starts_with(' '), "{line}");
+        }
+
+        let (contacts, _) = contacts_from_vcard(&vcard, None).unwrap();
+        assert_eq!(contacts[0].notes.as_deref(), Some(notes));
+    }
+
+    #[test]
+    fn vcard_2_1_keeps_commas_and_backslashes_in_structured_values() {
+        let contact = contact(
+            r#"[{
+                "name": {"first": "Zoë", "last": "Müller", "suffixes": ["Jr., MBA"]},
+                "addresses": [{
+                    "street": "Rue de la Paix, Bâtiment B",
+                    "number": "1",
+                    "locality": "Genève",
+                    "postal_code": "1201",
+                    "country": "CH"
+                }],
+                "notes": "C:\\Users\\zoë, déjà vu"
+            }]"#,
+        );
+        let vcard = export(&contact, VcardVersion::V2_1);
+        assert!(vcard.contains("ENCODING=QUOTED-PRINTABLE"));
+
+        let (contacts, _) = contacts_from_vcard(&vcard, None).unwrap();
+        assert_eq!(contacts[0].name.suffixes, contact.name.suffixes);
+        assert_eq!(contacts[0].addresses[0].street, contact.addresses[0].street);
+        assert_eq!(
+            contacts[0].addresses[0].locality,
+            contact.addresses[0].locality
+        );
+        assert_eq!(contacts[0].notes, contact.notes);
+    }
+
+    #[test]
+    fn export_leaves_out_vcard_4_0_properties_in_older_versions() {
+        let contact = import(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Anna\r\n\
+             UID:urn:uuid:ec6d9b4c-6a0b-4e42-9d1c-0b9d5c6e1f3a\r\n\
+             item1.RELATED;TYPE=x-boss:urn:uuid:00000000-0000-4000-8000-000000000000\r\n\
+             item1.X-ABLabel:Boss\r\n\
+             TEL;VALUE=uri;X-FOO=1:tel:+41791234567\r\n\
+             PHOTO:data:image/png;base64,AAAA\r\n\
+             X-CUSTOM:kept\r\n\
+             END:VCARD\r\n",
+        );
+
+        let vcard = export(&contact, VcardVersion::V4_0);
+        for property in ["RELATED", "X-ABLABEL", "TEL", "PHOTO", "X-CUSTOM"] {
+            assert!(vcard.contains(property), "{property} missing in {vcard}");
+        }
+        for version in [VcardVersion::V3_0, VcardVersion::V2_1] {
+            let vcard = export(&contact, version);
+            for property in ["RELATED", "X-ABLABEL", "TEL", "PHOTO"] {
+                assert!(!vcard.contains(property), "{property} in {vcard}");
+            }
+            assert!(vcard.contains("X-CUSTOM:kept"));
+        }
+    }
+
+    #[test]
+    fn export_writes_the_birthday_of_every_version() {
+        let contact = contact(r#"[{"name": {"first": "Anna"}, "bday": "-04-01"}]"#);
+        assert!(export(&contact, VcardVersion::V4_0).contains("BDAY:--0401\r\n"));
+        assert!(export(&contact, VcardVersion::V3_0)
+            .contains("BDAY;X-APPLE-OMIT-YEAR=1604:1604-04-01\r\n"));
+        let vcard = export(&contact, VcardVersion::V2_1);
+        assert!(vcard.starts_with("BEGIN:VCARD\r\nVERSION:2.1\r\n"));
+        assert!(vcard.contains("BDAY;X-APPLE-OMIT-YEAR=1604:1604-04-01\r\n"));
+    }
+
+    #[test]
+    fn import_decodes_quoted_printable() {
+        let contact = import(
+            "BEGIN:VCARD\r\nVERSION:2.1\r\nN:Muster;Anna\r\n\
+             NOTE;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:Z=C3=BCrich=0D=0Ana=\r\n\
+             ch Bern\r\n\
+             END:VCARD\r\n",
+        );
+        assert_eq!(contact.notes.as_deref(), Some("Zürich\nnach Bern"));
+        assert!(contact.vcard_properties.is_empty());
+    }
+
+    #[test]
+    fn split_dir_only_touches_exported_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = VcardOptions {
+            groups: false,
+            photo_mode: PhotoMode::Embed,
+            photos_path: PathBuf::new(),
+            derive_time_zone: false,
+            version: VcardVersion::V4_0,
+        };
+        let anna = contact(r#"[{"uid": "urn:uuid:a", "name": {"first": "Anna"}}]"#);
+        let bob = contact(r#"[{"uid": "urn:uuid:b", "name": {"first": "Bob"}}]"#);
+        fs::write(dir.path().join("other.vcf"), "not written by tactful").unwrap();
+
+        let summary = contacts_to_vcard_dir(dir.path(), [&anna, &bob], &options, true).unwrap();
+        assert_eq!(
+            (summary.written, summary.unchanged, summary.removed),
+            (2, 0, 0)
+        );
+
+        // Filtered exports keep the files of the other contacts
+        let summary = contacts_to_vcard_dir(dir.path(), [&anna], &options, false).unwrap();
+        assert_eq!(
+            (summary.written, summary.unchanged, summary.removed),
+            (0, 1, 0)
+        );
+        assert!(dir.path().join("b.vcf").exists());
+
+        let summary = contacts_to_vcard_dir(dir.path(), [&anna], &options, true).unwrap();
+        assert_eq!(
+            (summary.written, summary.unchanged, summary.removed),
+            (0, 1, 1)
+        );
+        assert!(!dir.path().join("b.vcf").exists());
+        assert!(dir.path().join("a.vcf").exists());
+        assert!(dir.path().join("other.vcf").exists());
+    }
+
+    #[test]
+    fn import_keeps_what_it_cannot_represent() {
+        let contact = import(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Muster;Anna;;;\r\n\
+             X-SOCIALPROFILE;TYPE=twitter:http://twitter.com/anna\r\n\
+             IMPP;X-SERVICE-TYPE=Skype:skype:anna\r\n\
+             PHOTO;VALUE=uri:https://example.org/anna.jpg\r\n\
+             X-ANDROID-CUSTOM:vnd.android.cursor.item/nickname;Anni;1\r\n\
+             X-SHOE-SIZE:38\r\n\
+             END:VCARD\r\n",
+        );
+        assert!(contact.social_profiles.is_empty());
+        assert!(contact.impp.is_empty());
+        assert!(contact.photo.is_none());
+        assert_eq!(
+            contact.extra.get("shoe-size").map(String::as_str),
+            Some("38")
+        );
+        assert_eq!(contact.vcard_properties.len(), 4);
+    }
+
+    #[test]
+    fn reserved_x_properties_are_not_custom_fields() {
+        let (contacts, warnings) = contacts_from_vcard(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Muster;Anna;;;\r\n\
+             X-ADDRESSBOOKSERVER-KIND:individual\r\n\
+             X-ABLABEL:Holiday home\r\n\
+             END:VCARD\r\n",
+            None,
+        )
+        .unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(contacts[0].extra.is_empty());
+
+        let vcard = export(&contacts[0], VcardVersion::V3_0);
+        assert_eq!(vcard.matches("X-ADDRESSBOOKSERVER-KIND").count(), 1);
+        let (contacts, diagnostics) = json::contacts_from_json_lenient(
+            r#"[{"name": {"first": "Anna"}, "extra": {"ablabel": "Home"}}]"#,
+            None,
+        );
+        assert!(contacts.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn import_leaves_missing_uid_empty() {
+        let contact = import("BEGIN:VCARD\r\nVERSION:3.0\r\nN:Muster;Anna;;;\r\nEND:VCARD\r\n");
+        assert!(contact.uid.is_empty());
+        assert_eq!(contact.name.to_string(), "Anna Muster");
+    }
+}